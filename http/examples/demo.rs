@@ -1,39 +1,92 @@
-use http::{ApiHandler, App, Request, Response, async_trait};
-use hyper::{Method, StatusCode};
+use http::{App, Error, Middleware, Next, Request, Response, Router, async_trait};
+use hyper::StatusCode;
+use serde::Deserialize;
+use std::time::Instant;
 
-struct H;
+// Prints every request along with the time it took to handle it
+struct Logger;
 
 #[async_trait]
-impl ApiHandler for H {
-    async fn incoming(&self, req: Request) -> Result<Response, hyper::Error> {
-        let method = req.method();
-        let segments = req.segments();
-
-        match (method, &segments[..]) {
-            // GET /
-            (&Method::GET, []) => Ok(Response::empty()
-                .status(StatusCode::OK)
-                .text("Is this the '/' page?")),
-
-            // GET /hello/{id}
-            (&Method::GET, ["hello", name]) => Ok(Response::empty()
-                .status(StatusCode::OK)
-                .text(format!("Hello {}!", name))),
-
-            // Any other
-            _ => Ok(Response::empty().status(StatusCode::NOT_FOUND)),
-        }
+impl Middleware for Logger {
+    async fn handle(&self, req: Request, next: Next<'_>) -> Result<Response, Error> {
+        let start = Instant::now();
+        let line = format!("{} {}", req.method(), req.uri().path());
+
+        let res = next.run(req).await?;
+
+        println!("{} -> {} ({:?})", line, (*res).status(), start.elapsed());
+        Ok(res)
     }
 }
 
+// GET /
+async fn index(_: Request) -> Result<Response, Error> {
+    Ok(Response::empty()
+        .status(StatusCode::OK)
+        .text("Is this the '/' page?"))
+}
+
+// GET /hello/:name
+async fn hello(req: Request) -> Result<Response, Error> {
+    let name = req.param::<String>("name").unwrap_or_default();
+
+    Ok(Response::empty()
+        .status(StatusCode::OK)
+        .text(format!("Hello {}!", name)))
+}
+
+#[derive(Deserialize)]
+struct Pagination {
+    page: Option<u32>,
+    limit: Option<u32>,
+}
+
+// GET /rooms?page=2&limit=50
+async fn rooms(req: Request) -> Result<Response, Error> {
+    let Pagination { page, limit } = req.query()?;
+
+    Ok(Response::empty().status(StatusCode::OK).text(format!(
+        "Page {} of rooms, {} per page",
+        page.unwrap_or(1),
+        limit.unwrap_or(20)
+    )))
+}
+
+// GET /rooms/:id/seats/:seat
+async fn seat(req: Request) -> Result<Response, Error> {
+    match (req.param::<u32>("id"), req.param::<u8>("seat")) {
+        (Some(id), Some(seat)) => Ok(Response::empty()
+            .status(StatusCode::OK)
+            .text(format!("Room {}, seat {}", id, seat))),
+
+        _ => Ok(Response::empty().status(StatusCode::BAD_REQUEST)),
+    }
+}
+
+// GET /files/*path
+async fn file(req: Request) -> Result<Response, Error> {
+    let path = req.param::<String>("path").unwrap_or_default();
+
+    Ok(Response::empty()
+        .status(StatusCode::OK)
+        .text(format!("You asked for '{}'", path)))
+}
+
 #[tokio::main]
 async fn main() {
     let addr = "127.0.0.1:5050";
 
+    let router = Router::new()
+        .get("/", index)
+        .get("/hello/:name", hello)
+        .get("/rooms", rooms)
+        .get("/rooms/:id/seats/:seat", seat)
+        .get("/files/*path", file);
+
     if let Ok(app) = App::new(addr.parse().expect("Invalid address")).await {
         println!("App listening on http://{}", addr);
 
-        if let Err(e) = app.run(H).await {
+        if let Err(e) = app.layer(Logger).run(router).await {
             eprintln!("Fatal error: {}", e);
         }
     }
@@ -0,0 +1,97 @@
+use crate::response::{Body, Response};
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::{
+    Method,
+    body::{Frame, SizeHint},
+};
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+use traccia::info;
+
+/// What gets logged about a request once its response has been sent.
+pub(crate) struct Entry {
+    pub(crate) id: String,
+    pub(crate) peer: Option<SocketAddr>,
+    pub(crate) method: Method,
+    pub(crate) path: String,
+    pub(crate) start: Instant,
+}
+
+impl Entry {
+    /// Logs the entry when the body of `res` has been fully sent, or dropped
+    /// because the client went away, so the latency covers the whole response.
+    pub(crate) fn attach(self, res: &mut Response) {
+        let status = (**res).status().as_u16();
+        let inner = res.take_body();
+
+        *res.body_mut() = Logged {
+            inner,
+            entry: Some((self, status)),
+            bytes: 0,
+        }
+        .boxed_unsync();
+    }
+}
+
+/// Counts the bytes of a response body, logging the request when it ends.
+struct Logged {
+    inner: Body,
+    entry: Option<(Entry, u16)>,
+    bytes: u64,
+}
+
+impl hyper::body::Body for Logged {
+    type Data = Bytes;
+    type Error = <Body as hyper::body::Body>::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(ref frame))) = poll
+            && let Some(data) = frame.data_ref()
+        {
+            self.bytes += data.len() as u64;
+        }
+
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for Logged {
+    fn drop(&mut self) {
+        let Some((entry, status)) = self.entry.take() else {
+            return;
+        };
+
+        let peer = entry
+            .peer
+            .map_or_else(|| "-".to_string(), |peer| peer.to_string());
+
+        info!(
+            "request_id={} peer={} method={} path={} status={} bytes={} latency={:.3}ms",
+            entry.id,
+            peer,
+            entry.method,
+            entry.path,
+            status,
+            self.bytes,
+            entry.start.elapsed().as_secs_f64() * 1000.0
+        );
+    }
+}
@@ -0,0 +1,890 @@
+use crate::{
+    access_log::Entry,
+    body::DEFAULT_BODY_LIMIT,
+    compression::Compression,
+    conditional::Conditions,
+    cors::Cors,
+    error::Error,
+    h2c,
+    idle::Tracked,
+    listener::Listener,
+    metrics::Metrics,
+    middleware::Stack,
+    request::{REQUEST_ID_HEADER, Request},
+    response::Response,
+    router::MatchedRoute,
+    state::StateMap,
+    tls::Tls,
+    traits::{ApiHandler, Middleware},
+    websocket::Session,
+};
+use bytes::Bytes;
+use hyper::{
+    Response as HyperResponse, StatusCode,
+    body::Incoming,
+    header::{ACCEPT_ENCODING, HeaderMap, HeaderValue, ORIGIN},
+    service::service_fn,
+    upgrade::OnUpgrade,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+};
+use std::{
+    convert::Infallible,
+    future::{pending, poll_fn},
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{
+        Semaphore,
+        mpsc::{self, UnboundedSender},
+        watch,
+    },
+    task::JoinSet,
+    time::sleep_until,
+};
+use traccia::{error, warn};
+
+type ShutdownSignal = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// How long in-flight connections are given to finish once shutdown starts, by default.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long clients have to send the headers of a request, by default.
+pub const DEFAULT_HEADER_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a connection can stay without any traffic before being closed, by default.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The HTTP versions an [`App`] speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    /// HTTP/1.1 only
+    #[default]
+    Http1,
+
+    /// HTTP/2 only, over cleartext with prior knowledge (h2c)
+    Http2,
+
+    /// Detects the version of each connection from its first bytes,
+    /// serving HTTP/2 to clients that open with the HTTP/2 preface
+    /// and HTTP/1.1 to everyone else.
+    ///
+    /// Cleartext HTTP/1.1 clients can also switch to HTTP/2 with `Upgrade: h2c`,
+    /// as long as the request asking for it has no body.
+    Auto,
+}
+
+impl Protocol {
+    fn builder(self, header_read_timeout: Option<Duration>) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(header_read_timeout);
+        builder.http2().timer(TokioTimer::new());
+
+        match self {
+            Protocol::Http1 => builder.http1_only(),
+            Protocol::Http2 => builder.http2_only(),
+            Protocol::Auto => builder,
+        }
+    }
+
+    /// Protocols advertised through ALPN during TLS handshakes.
+    pub(crate) fn alpn(self) -> Vec<Vec<u8>> {
+        match self {
+            Protocol::Http1 => vec![b"http/1.1".to_vec()],
+            Protocol::Http2 => vec![b"h2".to_vec()],
+            Protocol::Auto => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        }
+    }
+}
+
+/// What happened to the open connections when an [`App`] shut down.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShutdownReport {
+    /// Connections open at shutdown that finished before the deadline
+    pub drained: usize,
+
+    /// Connections that were still open at the deadline and got closed
+    pub forced: usize,
+}
+
+pub struct App {
+    listeners: Vec<Listener>,
+    layers: Vec<Box<dyn Middleware>>,
+    body_limit: usize,
+    cors: Option<Cors>,
+    compression: Option<Compression>,
+    shutdown: Option<ShutdownSignal>,
+    shutdown_timeout: Duration,
+    protocol: Protocol,
+    tls: Option<Tls>,
+    access_log: bool,
+    max_connections: Option<usize>,
+    header_read_timeout: Option<Duration>,
+    handler_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    state: StateMap,
+    metrics: Option<Metrics>,
+}
+
+impl App {
+    /// Binds a TCP socket to `addr`, see [`App::from_listeners`] to use other sockets.
+    pub async fn new(addr: SocketAddr) -> tokio::io::Result<Self> {
+        Ok(Self::from_listeners([Listener::bind(addr).await?]))
+    }
+
+    /// Serves connections from every listener, such as several addresses,
+    /// Unix domain sockets, or the sockets of [`Listener::from_env`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use http::{App, Listener};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> tokio::io::Result<()> {
+    ///     // Socket activated, or binding our own socket when started by hand
+    ///     let mut listeners = Listener::from_env()?;
+    ///     if listeners.is_empty() {
+    ///         listeners.push(Listener::bind("127.0.0.1:8080".parse().unwrap()).await?);
+    ///     }
+    ///
+    ///     let app = App::from_listeners(listeners);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_listeners<I: IntoIterator<Item = Listener>>(listeners: I) -> Self {
+        Self {
+            listeners: listeners.into_iter().collect(),
+            layers: Vec::new(),
+            body_limit: DEFAULT_BODY_LIMIT,
+            cors: None,
+            compression: None,
+            shutdown: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            protocol: Protocol::default(),
+            tls: None,
+            access_log: true,
+            max_connections: None,
+            header_read_timeout: Some(DEFAULT_HEADER_READ_TIMEOUT),
+            handler_timeout: None,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            state: StateMap::default(),
+            metrics: None,
+        }
+    }
+
+    /// Also serves connections from `listener`.
+    pub fn listener<L: Into<Listener>>(mut self, listener: L) -> Self {
+        self.listeners.push(listener.into());
+        self
+    }
+
+    /// The sockets the app accepts connections on.
+    pub fn listeners(&self) -> &[Listener] {
+        &self.listeners
+    }
+
+    /// Adds a middleware around the handler passed to [`App::run`].
+    ///
+    /// Middlewares run in the order they are added:
+    /// the first one sees the request first and the response last.
+    pub fn layer<M: Middleware>(mut self, middleware: M) -> Self {
+        self.layers.push(Box::new(middleware));
+        self
+    }
+
+    /// Sets the maximum request body size, in bytes, for every request.
+    ///
+    /// Defaults to [`DEFAULT_BODY_LIMIT`]. Single routes can override it with [`BodyLimit`](crate::BodyLimit).
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// Sets the CORS policy of the app.
+    ///
+    /// Without a policy, no `Access-Control-*` header is ever sent
+    /// and preflight requests reach the handler like any other request.
+    pub fn cors(mut self, cors: Cors) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+
+    /// Compresses responses with the encoding negotiated from `Accept-Encoding`.
+    ///
+    /// Without it, responses are always sent as the handler built them.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Sets the HTTP versions served by the app.
+    ///
+    /// Defaults to [`Protocol::Http1`].
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Serves connections over TLS.
+    pub fn tls(mut self, tls: Tls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Whether to log a line for every request, enabled by default.
+    ///
+    /// Lines are logged through `traccia` at the info level once the response
+    /// has been sent, with the request id, peer address, method, path, status,
+    /// body bytes sent and latency.
+    pub fn access_log(mut self, enabled: bool) -> Self {
+        self.access_log = enabled;
+        self
+    }
+
+    /// Registers a value shared by every request, one per type.
+    ///
+    /// Handlers and middlewares get it back with [`Request::state`] or the
+    /// [`State`](crate::extract::State) extractor, so pools, caches and registries
+    /// don't have to live in globals. Registering another value of the same type
+    /// replaces the previous one.
+    ///
+    /// Values only needed by a single request, such as the user found by an
+    /// authentication middleware, belong in the request extensions instead,
+    /// see [`Extension`](crate::extract::Extension).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use http::{App, Error, Response, Router, extract::State, handler};
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// #[derive(Default)]
+    /// struct Hits(AtomicU64);
+    ///
+    /// async fn hit(State(hits): State<Hits>) -> Result<Response, Error> {
+    ///     let n = hits.0.fetch_add(1, Ordering::Relaxed) + 1;
+    ///     Response::empty().body(n.to_string())
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> tokio::io::Result<()> {
+    ///     App::new("127.0.0.1:5050".parse().unwrap())
+    ///         .await?
+    ///         .state(Hits::default())
+    ///         .run(Router::new().get("/hit", handler(hit)))
+    ///         .await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn state<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.state.insert(value);
+        self
+    }
+
+    /// Records Prometheus metrics about requests and connections,
+    /// served on the path of `metrics`, see [`Metrics`].
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Sets a future that starts a graceful shutdown when it completes.
+    ///
+    /// Once the signal fires, the app stops accepting new connections and
+    /// lets the open ones finish their current request, waiting at most
+    /// the [shutdown timeout](App::shutdown_timeout) before closing them.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use http::{App, Router};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> tokio::io::Result<()> {
+    ///     let report = App::new("127.0.0.1:5050".parse().unwrap())
+    ///         .await?
+    ///         .shutdown_signal(async {
+    ///             tokio::signal::ctrl_c().await.ok();
+    ///         })
+    ///         .run(Router::new())
+    ///         .await?;
+    ///
+    ///     println!("{} connections were force-closed", report.forced);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn shutdown_signal<F: Future<Output = ()> + Send + 'static>(mut self, signal: F) -> Self {
+        self.shutdown = Some(Box::pin(signal));
+        self
+    }
+
+    /// Sets how long open connections are given to finish during a graceful shutdown.
+    ///
+    /// Defaults to [`DEFAULT_SHUTDOWN_TIMEOUT`].
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Limits how many connections are served at once, unlimited by default.
+    ///
+    /// Once the limit is reached, new connections are not accepted until
+    /// an open one is closed. They wait in the backlog of the listener,
+    /// where the operating system eventually refuses them.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Sets how long clients have to send the whole head of a request
+    /// over HTTP/1, including while a kept-alive connection waits for the next one.
+    /// It also bounds the TLS handshake, falling back to the idle timeout when `None`.
+    ///
+    /// Protects against clients that open connections and trickle headers
+    /// to keep them busy. Defaults to [`DEFAULT_HEADER_READ_TIMEOUT`], `None` disables it.
+    pub fn header_read_timeout<T: Into<Option<Duration>>>(mut self, timeout: T) -> Self {
+        self.header_read_timeout = timeout.into();
+        self
+    }
+
+    /// Sets how long the handler has to produce a response, no limit by default.
+    ///
+    /// Requests taking longer are answered with `504 Gateway Timeout`.
+    /// Only the time until the response is returned counts: streamed bodies
+    /// and WebSocket sessions can go on afterwards.
+    pub fn handler_timeout<T: Into<Option<Duration>>>(mut self, timeout: T) -> Self {
+        self.handler_timeout = timeout.into();
+        self
+    }
+
+    /// Sets how long a connection can go without reading or writing a byte
+    /// before it's gracefully closed, letting an in-flight request finish.
+    ///
+    /// A connection still idle for as long once closing, such as one whose
+    /// request body stalled, is dropped without waiting for the request.
+    ///
+    /// Defaults to [`DEFAULT_IDLE_TIMEOUT`], `None` disables it.
+    /// Upgraded connections, such as WebSockets, are not affected.
+    pub fn idle_timeout<T: Into<Option<Duration>>>(mut self, timeout: T) -> Self {
+        self.idle_timeout = timeout.into();
+        self
+    }
+
+    /// Serves connections until the shutdown signal fires, or forever if there is none.
+    ///
+    /// Returns early only if accepting a connection fails, the TLS configuration is invalid
+    /// or there is no listener.
+    pub async fn run<H: ApiHandler>(self, handler: H) -> tokio::io::Result<ShutdownReport> {
+        if self.listeners.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no listener to accept connections on",
+            ));
+        }
+
+        let shared = Arc::new(Shared {
+            handler: Stack::new(self.layers, handler),
+            body_limit: self.body_limit,
+            cors: self.cors,
+            compression: self.compression,
+            protocol: self.protocol,
+            access_log: self.access_log,
+            header_read_timeout: self.header_read_timeout,
+            handler_timeout: self.handler_timeout,
+            idle_timeout: self.idle_timeout,
+            state: Arc::new(self.state),
+            metrics: self.metrics,
+        });
+
+        let slots = self
+            .max_connections
+            .map(|max| Arc::new(Semaphore::new(max)));
+
+        let acceptor = match self.tls {
+            Some(ref tls) => Some(tls.acceptor(self.protocol.alpn())?),
+            None => None,
+        };
+
+        let reloader = self.tls.as_ref().and_then(Tls::spawn_reloader);
+
+        let mut signal = self.shutdown.unwrap_or_else(|| Box::pin(pending()));
+        let (shutdown_tx, shutdown_rx) = watch::channel(());
+        let mut connections = JoinSet::new();
+
+        // Where to start polling the listeners, moved on every accept so none starves the others
+        let mut next = 0;
+
+        loop {
+            // Waits for a free slot before accepting, leaving extra clients in the backlog
+            let accept = async {
+                let slot = match slots {
+                    Some(ref slots) => Some(slots.clone().acquire_owned().await.unwrap()),
+                    None => None,
+                };
+
+                let accepted = poll_fn(|cx| {
+                    let count = self.listeners.len();
+
+                    for i in 0..count {
+                        let index = (next + i) % count;
+
+                        if let Poll::Ready(accepted) = self.listeners[index].poll_accept(cx) {
+                            return Poll::Ready((accepted, index));
+                        }
+                    }
+
+                    Poll::Pending
+                })
+                .await;
+
+                (accepted, slot)
+            };
+
+            let ((stream, peer), slot) = tokio::select! {
+                ((accepted, index), slot) = accept => {
+                    next = index + 1;
+                    (accepted?, slot)
+                }
+
+                // Reap finished connections, so the set doesn't grow forever
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+
+                _ = &mut signal => break,
+            };
+
+            let shared = shared.clone();
+            let acceptor = acceptor.clone();
+            let shutdown_rx = shutdown_rx.clone();
+
+            connections.spawn(async move {
+                // Released when the connection ends
+                let _slot = slot;
+
+                // The handshake happens here rather than in the accept loop,
+                // so slow clients don't hold up everyone else
+                let Some(acceptor) = acceptor else {
+                    return shared
+                        .serve_connection(stream, peer, shutdown_rx, true)
+                        .await;
+                };
+
+                // Without a deadline, a client that never sends its hello would keep its slot forever
+                let handshake = acceptor.accept(stream);
+                let result = match shared.header_read_timeout.or(shared.idle_timeout) {
+                    Some(timeout) => tokio::time::timeout(timeout, handshake)
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                "the client took too long",
+                            ))
+                        }),
+                    None => handshake.await,
+                };
+
+                match result {
+                    Ok(stream) => {
+                        shared
+                            .serve_connection(stream, peer, shutdown_rx, false)
+                            .await
+                    }
+                    Err(e) => warn!("TLS handshake with {} failed: {}", describe(peer), e),
+                }
+            });
+        }
+
+        // Stop accepting new connections right away
+        drop(self.listeners);
+        _ = shutdown_tx.send(());
+
+        if let Some(reloader) = reloader {
+            reloader.abort();
+        }
+
+        let mut report = ShutdownReport::default();
+
+        let drain = async {
+            while connections.join_next().await.is_some() {
+                report.drained += 1;
+            }
+        };
+
+        if tokio::time::timeout(self.shutdown_timeout, drain)
+            .await
+            .is_err()
+        {
+            report.forced = connections.len();
+            connections.shutdown().await;
+        }
+
+        Ok(report)
+    }
+}
+
+/// Everything needed to answer a request, shared by all connections.
+struct Shared<H> {
+    handler: Stack<H>,
+    body_limit: usize,
+    cors: Option<Cors>,
+    compression: Option<Compression>,
+    protocol: Protocol,
+    access_log: bool,
+    header_read_timeout: Option<Duration>,
+    handler_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    state: Arc<StateMap>,
+    metrics: Option<Metrics>,
+}
+
+impl<H: ApiHandler> Shared<H> {
+    /// Serves the requests of a single connection until it's closed,
+    /// or gracefully closes it when shutdown starts.
+    ///
+    /// If the connection gets upgraded (e.g. to a WebSocket),
+    /// the upgraded session keeps running here until it ends.
+    async fn serve_connection<S>(
+        self: Arc<Self>,
+        stream: S,
+        peer: Option<SocketAddr>,
+        shutdown_rx: watch::Receiver<()>,
+        cleartext: bool,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        // Upgraded sessions run here too, so they count as open until they end
+        let _open = self.metrics.as_ref().map(Metrics::connection);
+
+        let (sessions_tx, mut sessions_rx) = mpsc::unbounded_channel();
+
+        let protocol = self.protocol;
+        self.drive(stream, peer, shutdown_rx, protocol, cleartext, sessions_tx)
+            .await;
+
+        while let Some(session) = sessions_rx.recv().await {
+            session.await;
+        }
+    }
+
+    /// Serves requests over `stream` with `protocol` until the connection is closed.
+    ///
+    /// Cleartext HTTP/1.1 connections can be upgraded to HTTP/2 when both versions are
+    /// spoken, in which case the HTTP/2 connection is queued as one of the sessions.
+    async fn drive<S>(
+        self: Arc<Self>,
+        stream: S,
+        peer: Option<SocketAddr>,
+        mut shutdown_rx: watch::Receiver<()>,
+        protocol: Protocol,
+        cleartext: bool,
+        sessions_tx: UnboundedSender<Session>,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let shared = self.clone();
+        let upgrade_rx = shutdown_rx.clone();
+
+        let service = service_fn(move |mut req| {
+            let shared = shared.clone();
+            let sessions = sessions_tx.clone();
+            let shutdown_rx = upgrade_rx.clone();
+
+            // TLS connections pick their version with ALPN instead
+            let stream_one = (cleartext && protocol == Protocol::Auto)
+                .then(|| h2c::upgrade(&req))
+                .flatten();
+
+            async move {
+                if let Some(stream_one) = stream_one {
+                    let on_upgrade = hyper::upgrade::on(&mut req);
+                    let session = shared.upgrade_to_h2c(
+                        on_upgrade,
+                        stream_one,
+                        peer,
+                        shutdown_rx,
+                        sessions.clone(),
+                    );
+
+                    if let Err(rejected) = sessions.send(session) {
+                        tokio::spawn(rejected.0);
+                    }
+
+                    return Ok(HyperResponse::from(h2c::switching_protocols()));
+                }
+
+                let response = shared.serve(req, peer, sessions).await;
+                Ok::<_, Infallible>(HyperResponse::from(response))
+            }
+        });
+
+        let (stream, activity) = Tracked::new(stream);
+
+        let builder = protocol.builder(self.header_read_timeout);
+        let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+        tokio::pin!(conn);
+
+        // Set once the connection is closed for being idle, as a request in progress
+        // (e.g. one whose body stalled) would otherwise keep it open forever
+        let mut closing: Option<tokio::time::Instant> = None;
+
+        let result = loop {
+            let deadline = |timeout| {
+                let deadline = activity.deadline(timeout);
+                closing.map_or(deadline, |since| deadline.max(since + timeout))
+            };
+
+            let idle = async {
+                match self.idle_timeout {
+                    Some(timeout) => sleep_until(deadline(timeout)).await,
+                    None => pending().await,
+                }
+            };
+
+            tokio::select! {
+                result = conn.as_mut() => break result,
+
+                // Finish the current request, then close the connection
+                _ = shutdown_rx.changed() => {
+                    conn.as_mut().graceful_shutdown();
+                    break conn.await;
+                }
+
+                // The deadline moves with traffic, so it may have passed in the meantime
+                _ = idle => {
+                    let timeout = self.idle_timeout.unwrap_or_default();
+                    let now = tokio::time::Instant::now();
+
+                    if deadline(timeout) > now {
+                        continue;
+                    }
+
+                    if closing.is_some() {
+                        break Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "the connection stayed idle while closing",
+                        )
+                        .into());
+                    }
+
+                    conn.as_mut().graceful_shutdown();
+                    closing = Some(now);
+                }
+            }
+        };
+
+        if let Err(e) = result {
+            warn!("Error serving connection from {}: {:?}", describe(peer), e);
+        }
+    }
+
+    /// The session serving a connection over HTTP/2 once it has switched from HTTP/1.1,
+    /// starting with the request that asked for it.
+    fn upgrade_to_h2c(
+        self: Arc<Self>,
+        on_upgrade: OnUpgrade,
+        stream_one: Bytes,
+        peer: Option<SocketAddr>,
+        shutdown_rx: watch::Receiver<()>,
+        sessions: UnboundedSender<Session>,
+    ) -> Session {
+        Box::pin(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    warn!("Upgrade to h2c from {} failed: {}", describe(peer), e);
+                    return;
+                }
+            };
+
+            let stream = h2c::Upgraded::new(TokioIo::new(upgraded), stream_one);
+
+            self.drive(stream, peer, shutdown_rx, Protocol::Http2, false, sessions)
+                .await;
+        })
+    }
+
+    async fn serve(
+        &self,
+        req: hyper::Request<Incoming>,
+        peer: Option<SocketAddr>,
+        sessions: UnboundedSender<Session>,
+    ) -> Response {
+        let start = Instant::now();
+
+        let mut req = Request::from(req);
+        req.set_body_limit(self.body_limit);
+        req.set_sessions(sessions);
+        if let Some(peer) = peer {
+            req.set_peer_addr(peer);
+        }
+
+        req.set_state(self.state.clone());
+
+        let id = req.request_id().to_string();
+
+        let entry = self.access_log.then(|| Entry {
+            id: id.clone(),
+            peer,
+            method: req.method().clone(),
+            path: req.uri().path().to_string(),
+            start,
+        });
+
+        let origin = req.headers().get(ORIGIN).cloned();
+
+        // Kept aside to negotiate the encoding, the request is moved into the handler
+        let mut accept = HeaderMap::new();
+        if self.compression.is_some() {
+            for value in req.headers().get_all(ACCEPT_ENCODING) {
+                accept.append(ACCEPT_ENCODING, value.clone());
+            }
+        }
+
+        // Scrapes are answered directly and left out of the metrics they read
+        let scrape = self
+            .metrics
+            .as_ref()
+            .filter(|metrics| metrics.is_scrape(&req));
+
+        let method = req.method().clone();
+        let conditions = Conditions::new(req.headers());
+        let route = MatchedRoute::default();
+
+        let recording = match self.metrics {
+            Some(ref metrics) if scrape.is_none() => {
+                req.extensions_mut().insert(route.clone());
+                Some((metrics, metrics.in_flight()))
+            }
+            _ => None,
+        };
+
+        let result = match (scrape, &self.cors) {
+            (Some(metrics), _) => metrics.incoming(req).await,
+            (None, Some(cors)) if Cors::is_preflight(&req) => cors.preflight(&req),
+            _ => match self.handler_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, self.handler.incoming(req))
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(Error::new(
+                            StatusCode::GATEWAY_TIMEOUT,
+                            "timeout",
+                            "The request took too long to handle",
+                        ))
+                    }),
+                None => self.handler.incoming(req).await,
+            },
+        };
+
+        let response = match result {
+            Ok(response) => response,
+            Err(err) => {
+                if err.status().is_server_error() {
+                    let peer = peer.map_or_else(|| "-".to_string(), |peer| peer.to_string());
+                    error!("request_id={} peer={} {}", id, peer, err);
+                }
+
+                err.into()
+            }
+        };
+
+        // Before compression, which turns strong tags into weak ones
+        let mut response = conditions.apply(&method, response);
+
+        if let Some((metrics, _in_flight)) = recording {
+            metrics.record(&method, &route, (*response).status(), start.elapsed());
+        }
+
+        if let Some(ref cors) = self.cors {
+            cors.decorate(origin.as_ref(), &mut response);
+        }
+
+        if let Some(ref compression) = self.compression {
+            compression.encode(&accept, &mut response);
+        }
+
+        if let Ok(id) = HeaderValue::from_str(&id) {
+            response.headers_mut().insert(REQUEST_ID_HEADER, id);
+        }
+
+        if let Some(entry) = entry {
+            entry.attach(&mut response);
+        }
+
+        response
+    }
+}
+
+/// Names the client of a connection in log messages.
+fn describe(peer: Option<SocketAddr>) -> String {
+    peer.map_or_else(|| "unix socket".to_string(), |peer| peer.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    const IDLE: Duration = Duration::from_millis(200);
+
+    async fn spawn() -> SocketAddr {
+        let app = App::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .idle_timeout(IDLE);
+        let addr = app.listeners()[0].local_addr().unwrap();
+
+        let echo = |req: Request| async move {
+            let body = req.bytes().await?;
+            Ok(Response::empty().text(String::from_utf8_lossy(&body).into_owned()))
+        };
+
+        tokio::spawn(app.run(Router::new().post("/echo", echo)));
+        addr
+    }
+
+    /// Waits for the server to close `stream`, failing if it takes much longer than the timeout.
+    async fn assert_closed(stream: &mut TcpStream) {
+        let mut read = Vec::new();
+        let closed = tokio::time::timeout(IDLE * 10, stream.read_to_end(&mut read)).await;
+
+        assert!(closed.is_ok(), "the connection is still open");
+    }
+
+    #[tokio::test]
+    async fn closes_idle_connections() {
+        let mut stream = TcpStream::connect(spawn().await).await.unwrap();
+        assert_closed(&mut stream).await;
+    }
+
+    #[tokio::test]
+    async fn drops_connections_whose_body_stalled() {
+        let mut stream = TcpStream::connect(spawn().await).await.unwrap();
+
+        stream
+            .write_all(
+                b"POST /echo HTTP/1.1\r\nhost: casino.test\r\ncontent-length: 100\r\n\r\nabc",
+            )
+            .await
+            .unwrap();
+
+        assert_closed(&mut stream).await;
+    }
+}
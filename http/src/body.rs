@@ -0,0 +1,142 @@
+use crate::{
+    error::{BoxError, Error},
+    request::Request,
+    response::Response,
+    traits::ApiHandler,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http_body_util::{BodyExt, combinators::BoxBody};
+use hyper::StatusCode;
+
+/// The body of a [`Request`], either streamed from a connection or built in memory.
+pub type RequestBody = BoxBody<Bytes, BoxError>;
+
+/// Maximum request body size used when nothing else is configured, 2 MiB.
+pub const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+pub(crate) fn too_large(limit: usize) -> Error {
+    Error::new(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "payload_too_large",
+        format!("Request body exceeds the limit of {} bytes", limit),
+    )
+}
+
+/// A stream over the chunks of a request body, as they arrive from the client.
+///
+/// Obtained with [`Request::chunks`]. The size limit of the request
+/// is enforced while reading: once it's exceeded, [`Chunks::next`] yields
+/// a `413 Payload Too Large` error and the stream ends.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Request, Response};
+///
+/// async fn upload(req: Request) -> Result<Response, Error> {
+///     let mut chunks = req.chunks()?;
+///
+///     while let Some(chunk) = chunks.next().await {
+///         let chunk = chunk?;
+///         // write chunk somewhere...
+///     }
+///
+///     Ok(Response::empty())
+/// }
+/// ```
+pub struct Chunks {
+    body: RequestBody,
+    limit: usize,
+    read: usize,
+    done: bool,
+}
+
+impl Chunks {
+    pub(crate) fn new(body: RequestBody, limit: usize) -> Self {
+        Self {
+            body,
+            limit,
+            read: 0,
+            done: false,
+        }
+    }
+
+    /// Waits for the next chunk of data.
+    ///
+    /// Returns `None` once the body has been fully read or an error was returned.
+    pub async fn next(&mut self) -> Option<Result<Bytes, Error>> {
+        while !self.done {
+            let frame = match self.body.frame().await {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => {
+                    self.done = true;
+
+                    return Some(Err(Error::new(
+                        StatusCode::BAD_REQUEST,
+                        "body_read",
+                        "Failed to read the request body",
+                    )
+                    .with_source(e)));
+                }
+                None => break,
+            };
+
+            // Trailers carry no data, skip them
+            if let Ok(data) = frame.into_data() {
+                self.read += data.len();
+
+                if self.read > self.limit {
+                    self.done = true;
+                    return Some(Err(too_large(self.limit)));
+                }
+
+                return Some(Ok(data));
+            }
+        }
+
+        self.done = true;
+        None
+    }
+
+    /// Number of bytes read so far.
+    pub fn read(&self) -> usize {
+        self.read
+    }
+}
+
+/// Overrides the maximum body size for the wrapped handler.
+///
+/// Useful to allow bigger uploads on a single route, or to restrict
+/// routes that only expect tiny payloads.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{BodyLimit, Error, Request, Response, Router};
+///
+/// async fn avatar(req: Request) -> Result<Response, Error> {
+///     let image = req.bytes().await?;
+///     Ok(Response::empty())
+/// }
+///
+/// let router = Router::new().put("/avatar", BodyLimit::new(8 * 1024 * 1024, avatar));
+/// ```
+pub struct BodyLimit<H> {
+    limit: usize,
+    handler: H,
+}
+
+impl<H: ApiHandler> BodyLimit<H> {
+    pub fn new(limit: usize, handler: H) -> Self {
+        Self { limit, handler }
+    }
+}
+
+#[async_trait]
+impl<H: ApiHandler> ApiHandler for BodyLimit<H> {
+    async fn incoming(&self, mut req: Request) -> Result<Response, Error> {
+        req.set_body_limit(self.limit);
+        self.handler.incoming(req).await
+    }
+}
@@ -0,0 +1,400 @@
+use crate::response::Response;
+use async_compression::{
+    Level,
+    tokio::bufread::{BrotliEncoder, GzipEncoder, ZstdEncoder},
+};
+use futures_util::TryStreamExt;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::{
+    StatusCode,
+    body::{Body as _, Frame},
+    header::{
+        ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+        CONTENT_TYPE, ETAG, HeaderMap, HeaderValue, VARY,
+    },
+};
+use std::io;
+use tokio::io::AsyncRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// Responses smaller than this, in bytes, are sent uncompressed by default.
+pub const DEFAULT_MIN_COMPRESS_SIZE: u64 = 1024;
+
+/// Content types compressed by default. Images, videos and archives
+/// are already compressed, and event streams need every chunk flushed right away.
+const DEFAULT_CONTENT_TYPES: [&str; 8] = [
+    "text/html",
+    "text/css",
+    "text/plain",
+    "text/javascript",
+    "application/javascript",
+    "application/json",
+    "application/xml",
+    "image/svg+xml",
+];
+
+/// The encodings supported, in order of preference when the client likes them equally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// Response compression settings of an [`App`](crate::App).
+///
+/// The encoding is negotiated from the `Accept-Encoding` header of each
+/// request, picking the one with the highest quality among brotli, zstd
+/// and gzip. Responses are only compressed when they are big enough and
+/// their content type is in the allowlist, and never when they already
+/// have a `Content-Encoding` or are partial.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{App, Compression, Router};
+///
+/// #[tokio::main]
+/// async fn main() -> tokio::io::Result<()> {
+///     let compression = Compression::new()
+///         .zstd(false)
+///         .min_size(4096);
+///
+///     App::new("127.0.0.1:5050".parse().unwrap())
+///         .await?
+///         .compression(compression)
+///         .run(Router::new())
+///         .await?;
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Compression {
+    encodings: Vec<Encoding>,
+    min_size: u64,
+    content_types: Vec<String>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    /// Enables every encoding, with the default size threshold and content types.
+    pub fn new() -> Self {
+        Self {
+            encodings: vec![Encoding::Brotli, Encoding::Zstd, Encoding::Gzip],
+            min_size: DEFAULT_MIN_COMPRESS_SIZE,
+            content_types: DEFAULT_CONTENT_TYPES.map(String::from).to_vec(),
+        }
+    }
+
+    pub fn brotli(self, enabled: bool) -> Self {
+        self.toggle(Encoding::Brotli, enabled)
+    }
+
+    pub fn zstd(self, enabled: bool) -> Self {
+        self.toggle(Encoding::Zstd, enabled)
+    }
+
+    pub fn gzip(self, enabled: bool) -> Self {
+        self.toggle(Encoding::Gzip, enabled)
+    }
+
+    /// Sets the size, in bytes, under which responses are sent as they are.
+    ///
+    /// Streamed bodies of unknown size are always compressed.
+    /// Defaults to [`DEFAULT_MIN_COMPRESS_SIZE`].
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Replaces the content types that get compressed.
+    ///
+    /// Entries can end with `/*` to match a whole type, e.g. `text/*`.
+    pub fn content_types<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.content_types = types
+            .into_iter()
+            .map(|t| t.into().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    fn toggle(mut self, encoding: Encoding, enabled: bool) -> Self {
+        self.encodings.retain(|e| *e != encoding);
+
+        if enabled {
+            self.encodings.push(encoding);
+            self.encodings.sort_by_key(|e| *e as u8);
+        }
+
+        self
+    }
+
+    /// Picks the preferred enabled encoding accepted by the client.
+    fn negotiate(&self, headers: &HeaderMap) -> Option<Encoding> {
+        let mut best = None;
+        let mut best_quality = 0.0;
+
+        for &encoding in &self.encodings {
+            let quality = quality(headers, encoding.name());
+
+            if quality > best_quality {
+                best = Some(encoding);
+                best_quality = quality;
+            }
+        }
+
+        // A client preferring the body as it is gets it uncompressed
+        if quality(headers, "identity") > best_quality {
+            return None;
+        }
+
+        best
+    }
+
+    fn is_compressible(&self, res: &Response) -> bool {
+        let headers = res.headers();
+
+        let status = (**res).status();
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || status == StatusCode::PARTIAL_CONTENT
+        {
+            return false;
+        }
+
+        if headers.contains_key(CONTENT_ENCODING) || headers.contains_key(CONTENT_RANGE) {
+            return false;
+        }
+
+        let no_transform = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|d| d.trim().eq_ignore_ascii_case("no-transform"));
+
+        if no_transform {
+            return false;
+        }
+
+        let size = (**res).body().size_hint().exact().or_else(|| {
+            headers
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+        });
+
+        if size.is_some_and(|size| size < self.min_size) {
+            return false;
+        }
+
+        let Some(essence) = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase())
+        else {
+            return false;
+        };
+
+        self.content_types
+            .iter()
+            .any(|allowed| match allowed.strip_suffix("/*") {
+                Some(kind) => essence.split('/').next() == Some(kind),
+                None => *allowed == essence,
+            })
+    }
+
+    /// Compresses the body of `res` if the request `headers` and the response allow it.
+    pub(crate) fn encode(&self, headers: &HeaderMap, res: &mut Response) {
+        if !self.is_compressible(res) {
+            return;
+        }
+
+        // The response depends on the header even when it's sent uncompressed
+        let varies = res
+            .headers()
+            .get_all(VARY)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|v| v.trim().eq_ignore_ascii_case("accept-encoding"));
+
+        if !varies {
+            res.headers_mut()
+                .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+        }
+
+        let Some(encoding) = self.negotiate(headers) else {
+            return;
+        };
+
+        let body = res.take_body();
+        let reader = StreamReader::new(body.into_data_stream().map_err(io::Error::other));
+
+        let encoded: Box<dyn AsyncRead + Send + Unpin> = match encoding {
+            Encoding::Brotli => Box::new(BrotliEncoder::with_quality(reader, Level::Precise(4))),
+            Encoding::Zstd => Box::new(ZstdEncoder::new(reader)),
+            Encoding::Gzip => Box::new(GzipEncoder::new(reader)),
+        };
+
+        let frames = ReaderStream::new(encoded)
+            .map_ok(Frame::data)
+            .map_err(Into::into);
+
+        *res.body_mut() = StreamBody::new(frames).boxed_unsync();
+
+        let headers = res.headers_mut();
+        headers.remove(CONTENT_LENGTH);
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+
+        // The compressed bytes differ from the original ones, so the validator can only be weak
+        if let Some(etag) = headers.get(ETAG)
+            && !etag.as_bytes().starts_with(b"W/")
+        {
+            let mut weak = b"W/".to_vec();
+            weak.extend_from_slice(etag.as_bytes());
+
+            if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                headers.insert(ETAG, weak);
+            }
+        }
+    }
+}
+
+/// The quality `Accept-Encoding` gives to `encoding`, zero if it's not accepted.
+pub(crate) fn quality(headers: &HeaderMap, encoding: &str) -> f32 {
+    let mut wildcard = 0.0;
+
+    let items = headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','));
+
+    for item in items {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let quality = parts
+            .find_map(|p| p.strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.parse().ok())
+            .unwrap_or(0.0);
+
+        if name.eq_ignore_ascii_case(encoding) {
+            return quality;
+        }
+
+        if name == "*" {
+            wildcard = quality;
+        }
+    }
+
+    wildcard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepting(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(value));
+        headers
+    }
+
+    fn negotiate(value: &'static str) -> Option<Encoding> {
+        Compression::new().negotiate(&accepting(value))
+    }
+
+    #[test]
+    fn reads_qualities() {
+        let headers = accepting("gzip;q=0.5, br ; q=0.8, zstd");
+
+        assert_eq!(quality(&headers, "gzip"), 0.5);
+        assert_eq!(quality(&headers, "br"), 0.8);
+        assert_eq!(quality(&headers, "zstd"), 1.0);
+        assert_eq!(quality(&headers, "deflate"), 0.0);
+        assert_eq!(quality(&HeaderMap::new(), "gzip"), 0.0);
+    }
+
+    #[test]
+    fn picks_the_highest_quality() {
+        assert_eq!(negotiate("gzip, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(
+            negotiate("gzip;q=0.2, zstd;q=0.9, br;q=0.5"),
+            Some(Encoding::Zstd)
+        );
+
+        // Ties go to the order of preference of the server
+        assert_eq!(negotiate("gzip, zstd, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("deflate"), None);
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("br;q=0, *"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("gzip;q=0.8, *;q=0.1"), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn skips_refused_encodings() {
+        assert_eq!(negotiate("br;q=0, zstd;q=0, gzip;q=0"), None);
+        assert_eq!(negotiate("*;q=0"), None);
+        assert_eq!(negotiate("gzip;q=0, *;q=0.5"), Some(Encoding::Brotli));
+
+        let gzip_only = Compression::new().brotli(false).zstd(false);
+        assert_eq!(gzip_only.negotiate(&accepting("br, zstd")), None);
+    }
+
+    #[test]
+    fn honors_identity() {
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("identity, gzip;q=0.5"), None);
+        assert_eq!(negotiate("identity;q=0.5, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity;q=0, gzip;q=0.1"), Some(Encoding::Gzip));
+    }
+
+    fn encoded(compression: &Compression, len: usize) -> Option<String> {
+        let mut res = Response::empty().text("a".repeat(len));
+
+        compression.encode(&accepting("gzip"), &mut res);
+
+        res.headers()
+            .get(CONTENT_ENCODING)
+            .map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn leaves_small_bodies_alone() {
+        let compression = Compression::new();
+
+        assert_eq!(encoded(&compression, 1023), None);
+        assert_eq!(encoded(&compression, 1024).as_deref(), Some("gzip"));
+        assert_eq!(
+            encoded(&compression.min_size(10), 10).as_deref(),
+            Some("gzip")
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use crate::{error::Error, format::Format, response::Response};
+use httpdate::HttpDate;
+use hyper::{
+    HeaderMap, Method, StatusCode,
+    header::{
+        CONTENT_LENGTH, ETAG, HeaderValue, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        IF_UNMODIFIED_SINCE, LAST_MODIFIED,
+    },
+};
+use serde::Serialize;
+use std::{
+    fmt,
+    hash::{DefaultHasher, Hasher},
+    time::{Duration, SystemTime},
+};
+
+/// An entity tag, identifying a version of a resource in the `ETag` header.
+///
+/// Clients send it back in `If-None-Match` to revalidate their copy, which the
+/// [`App`](crate::App) answers with `304 Not Modified` when it's still current,
+/// or in `If-Match` to only act on the version they know.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{CacheControl, ETag, Error, Request, Response};
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Table {
+///     name: String,
+///     players: u8,
+/// }
+///
+/// // Polled by every client in the lobby, rarely changes
+/// async fn lobby(req: Request) -> Result<Response, Error> {
+///     let tables = vec![Table { name: "High rollers".into(), players: 3 }];
+///
+///     Response::empty()
+///         .etag(ETag::from_payload(&tables)?)
+///         .cache_control(CacheControl::new().private().no_cache())
+///         .body_as(req.preferred_format(), tables)
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ETag {
+    tag: String,
+    weak: bool,
+}
+
+impl ETag {
+    /// A strong tag, for representations that are identical byte for byte.
+    ///
+    /// # Panics
+    ///
+    /// If `tag` contains a double quote, a space or a non-ASCII character.
+    pub fn strong<T: Into<String>>(tag: T) -> Self {
+        Self::new(tag.into(), false)
+    }
+
+    /// A weak tag, for representations that are equivalent but may differ in their bytes.
+    ///
+    /// # Panics
+    ///
+    /// If `tag` contains a double quote, a space or a non-ASCII character.
+    pub fn weak<T: Into<String>>(tag: T) -> Self {
+        Self::new(tag.into(), true)
+    }
+
+    fn new(tag: String, weak: bool) -> Self {
+        assert!(
+            tag.bytes().all(|b| b == 0x21 || (0x23..=0x7e).contains(&b)),
+            "invalid entity tag: {}",
+            tag
+        );
+
+        Self { tag, weak }
+    }
+
+    /// A strong tag hashing `bytes`, such as a serialized body.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            tag: hash(bytes),
+            weak: false,
+        }
+    }
+
+    /// A weak tag hashing the JSON serialization of `payload`, so it stays
+    /// the same whatever [`Format`] the body is sent in.
+    ///
+    /// Fails with `500 Internal Server Error` if the payload can't be serialized.
+    pub fn from_payload<T: Serialize>(payload: &T) -> Result<Self, Error> {
+        let bytes = Format::Json.serialize(payload)?;
+
+        Ok(Self {
+            tag: hash(&bytes),
+            weak: true,
+        })
+    }
+
+    /// The opaque tag, without quotes nor the `W/` prefix.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Whether both tags are strong and the same, as `If-Match` compares them.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Whether both tags are the same, weak or not, as `If-None-Match` compares them.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Parses a single `"tag"` or `W/"tag"`.
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (weak, value) = match value.strip_prefix("W/") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+
+        let tag = value.strip_prefix('"')?.strip_suffix('"')?;
+
+        Some(Self {
+            tag: tag.to_string(),
+            weak,
+        })
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            write!(f, "W/")?;
+        }
+
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+impl From<ETag> for HeaderValue {
+    fn from(etag: ETag) -> Self {
+        // The characters of the tag are checked when it's created
+        HeaderValue::from_str(&etag.to_string()).unwrap()
+    }
+}
+
+/// Hashes `bytes` with fixed keys, so every instance running the same build
+/// gives the same tag and clients can revalidate against any of them.
+fn hash(bytes: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+
+    format!("{:x}-{:016x}", bytes.len(), hasher.finish())
+}
+
+/// The directives of a `Cache-Control` header.
+///
+/// For data that is polled and rarely changes, `no-cache` along an [`ETag`] makes
+/// clients revalidate every time, which costs an empty `304` while it's unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct CacheControl {
+    directives: Vec<String>,
+}
+
+impl CacheControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets shared caches, like a CDN, store the response.
+    pub fn public(self) -> Self {
+        self.directive("public")
+    }
+
+    /// Only lets the client store the response, e.g. when it depends on the session.
+    pub fn private(self) -> Self {
+        self.directive("private")
+    }
+
+    /// Requires stored copies to be revalidated before each use.
+    pub fn no_cache(self) -> Self {
+        self.directive("no-cache")
+    }
+
+    /// Forbids storing the response at all, for sensitive data.
+    pub fn no_store(self) -> Self {
+        self.directive("no-store")
+    }
+
+    /// Sets how long the response stays fresh, with a one second resolution.
+    pub fn max_age(self, age: Duration) -> Self {
+        self.directive(format!("max-age={}", age.as_secs()))
+    }
+
+    /// Forbids using a stale copy once it can't be revalidated.
+    pub fn must_revalidate(self) -> Self {
+        self.directive("must-revalidate")
+    }
+
+    /// Tells the client the response never changes while fresh, e.g. for fingerprinted assets.
+    pub fn immutable(self) -> Self {
+        self.directive("immutable")
+    }
+
+    fn directive<D: Into<String>>(mut self, directive: D) -> Self {
+        self.directives.push(directive.into());
+        self
+    }
+}
+
+impl From<CacheControl> for HeaderValue {
+    fn from(cache: CacheControl) -> Self {
+        // Directives are fixed ASCII tokens
+        HeaderValue::from_str(&cache.directives.join(", ")).unwrap()
+    }
+}
+
+/// The conditional headers of a request, kept aside while the handler consumes it.
+#[derive(Debug, Default)]
+pub(crate) struct Conditions {
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<HttpDate>,
+    if_unmodified_since: Option<HttpDate>,
+}
+
+impl Conditions {
+    pub(crate) fn new(headers: &HeaderMap) -> Self {
+        let text = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+
+        // Invalid dates are ignored, as if the header was missing
+        let date = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .and_then(|v| v.parse::<HttpDate>().ok())
+        };
+
+        Self {
+            if_match: text(IF_MATCH),
+            if_none_match: text(IF_NONE_MATCH),
+            if_modified_since: date(IF_MODIFIED_SINCE),
+            if_unmodified_since: date(IF_UNMODIFIED_SINCE),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.if_match.is_none()
+            && self.if_none_match.is_none()
+            && self.if_modified_since.is_none()
+            && self.if_unmodified_since.is_none()
+    }
+
+    /// Evaluates the conditions in the order of RFC 9110 against the current version
+    /// of the resource, returning the status to answer with instead when one fails.
+    pub(crate) fn evaluate(
+        &self,
+        method: &Method,
+        exists: bool,
+        etag: Option<&ETag>,
+        modified: Option<SystemTime>,
+    ) -> Option<StatusCode> {
+        let modified = modified.map(HttpDate::from);
+
+        if let Some(ref tags) = self.if_match {
+            if !matches(tags, exists, etag, ETag::strong_eq) {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+        } else if let (Some(since), Some(modified)) = (self.if_unmodified_since, modified)
+            && modified > since
+        {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+
+        let safe = method == Method::GET || method == Method::HEAD;
+
+        if let Some(ref tags) = self.if_none_match {
+            if matches(tags, exists, etag, ETag::weak_eq) {
+                return Some(if safe {
+                    StatusCode::NOT_MODIFIED
+                } else {
+                    StatusCode::PRECONDITION_FAILED
+                });
+            }
+        } else if safe
+            && let (Some(since), Some(modified)) = (self.if_modified_since, modified)
+            && modified <= since
+        {
+            return Some(StatusCode::NOT_MODIFIED);
+        }
+
+        None
+    }
+
+    /// Answers a successful `GET` or `HEAD` with `304 Not Modified` or `412 Precondition Failed`
+    /// when the conditions fail on the `ETag` and `Last-Modified` of the response.
+    ///
+    /// Other methods are left alone, as the handler has already acted by then,
+    /// see [`Request::preconditions`](crate::Request::preconditions).
+    pub(crate) fn apply(&self, method: &Method, mut response: Response) -> Response {
+        let safe = method == Method::GET || method == Method::HEAD;
+
+        if self.is_empty() || !safe || !(*response).status().is_success() {
+            return response;
+        }
+
+        let headers = response.headers();
+        let etag = headers
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .and_then(ETag::parse);
+        let modified = headers
+            .get(LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+
+        match self.evaluate(method, true, etag.as_ref(), modified) {
+            Some(StatusCode::NOT_MODIFIED) => {
+                response.take_body();
+                response.headers_mut().remove(CONTENT_LENGTH);
+                response.status(StatusCode::NOT_MODIFIED)
+            }
+            Some(_) => precondition_failed().into(),
+            None => response,
+        }
+    }
+}
+
+pub(crate) fn precondition_failed() -> Error {
+    Error::new(
+        StatusCode::PRECONDITION_FAILED,
+        "precondition_failed",
+        "The resource doesn't match the conditions of the request",
+    )
+}
+
+/// Whether a list of entity tags, or `*`, matches the current version of the resource.
+fn matches(tags: &str, exists: bool, etag: Option<&ETag>, eq: fn(&ETag, &ETag) -> bool) -> bool {
+    if tags.trim() == "*" {
+        return exists;
+    }
+
+    let Some(etag) = etag else {
+        return false;
+    };
+
+    tags.split(',')
+        .filter_map(ETag::parse)
+        .any(|tag| eq(&tag, etag))
+}
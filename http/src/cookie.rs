@@ -0,0 +1,77 @@
+//! Reading cookies from requests and setting them on responses.
+//!
+//! Besides plain cookies, values can be signed (readable by the client,
+//! but tamper-proof) or private (encrypted and authenticated) with a
+//! [`Key`] only the server knows.
+
+use ::cookie::CookieJar;
+use hyper::header::{COOKIE, HeaderMap};
+
+pub use ::cookie::{Cookie, CookieBuilder, Expiration, Key, KeyError, SameSite, time::Duration};
+
+/// The cookies sent with a request.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Request, Response, cookie::Key};
+///
+/// async fn profile(req: Request, key: &Key) -> Result<Response, Error> {
+///     let cookies = req.cookies();
+///
+///     let theme = cookies.get("theme").map(|c| c.value().to_string());
+///     let session = cookies
+///         .get_private("session", key)
+///         .ok_or_else(|| Error::unauthorized("Not logged in"))?;
+///
+///     Ok(Response::empty().text(format!("{} {:?}", session.value(), theme)))
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Cookies {
+    jar: CookieJar,
+}
+
+impl Cookies {
+    /// Parses every `Cookie` header, skipping malformed pairs.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let mut jar = CookieJar::new();
+
+        let pairs = headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(';'))
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty());
+
+        for pair in pairs {
+            if let Ok(cookie) = Cookie::parse_encoded(pair.to_string()) {
+                jar.add_original(cookie);
+            }
+        }
+
+        Self { jar }
+    }
+
+    /// A plain cookie, as the client sent it.
+    pub fn get(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.jar.get(name)
+    }
+
+    /// A cookie set with [`Response::signed_cookie`](crate::Response::signed_cookie),
+    /// only if its signature is valid for `key`.
+    pub fn get_signed(&self, name: &str, key: &Key) -> Option<Cookie<'static>> {
+        self.jar.signed(key).get(name)
+    }
+
+    /// A cookie set with [`Response::private_cookie`](crate::Response::private_cookie),
+    /// decrypted with `key`, only if it wasn't tampered with.
+    pub fn get_private(&self, name: &str, key: &Key) -> Option<Cookie<'static>> {
+        self.jar.private(key).get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie<'static>> {
+        self.jar.iter()
+    }
+}
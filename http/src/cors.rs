@@ -0,0 +1,348 @@
+use crate::{error::Error, request::Request, response::Response};
+use hyper::{
+    Method, StatusCode,
+    header::{
+        ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+        ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD,
+        HeaderName, HeaderValue, ORIGIN, VARY,
+    },
+};
+use std::{sync::Arc, time::Duration};
+
+enum Origins {
+    Any,
+    List(Vec<String>),
+    Predicate(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+/// Cross-origin resource sharing policy of an [`App`](crate::App).
+///
+/// Once set with [`App::cors`](crate::App::cors), the app answers preflight requests
+/// by itself, rejecting the ones coming from origins or asking for methods and headers
+/// that aren't allowed with `403 Forbidden`. Responses to allowed origins are decorated
+/// with the matching `Access-Control-*` headers, while the others are left untouched
+/// so that the browser blocks them.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::Cors;
+/// use std::time::Duration;
+///
+/// let cors = Cors::new()
+///     .allow_origin("http://localhost:5173")
+///     .allow_credentials(true)
+///     .expose_headers(["x-request-id"])
+///     .max_age(Duration::from_secs(600));
+/// ```
+pub struct Cors {
+    origins: Origins,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    exposed: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Creates a policy that allows no origin.
+    ///
+    /// The usual methods (`GET`, `POST`, `PUT`, `PATCH`, `DELETE`) and
+    /// request headers (`Accept`, `Accept-Language`, `Content-Language`,
+    /// `Content-Type`, `Authorization`) are allowed by default.
+    pub fn new() -> Self {
+        Self {
+            origins: Origins::List(Vec::new()),
+            methods: vec![
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ],
+            headers: vec![
+                hyper::header::ACCEPT,
+                hyper::header::ACCEPT_LANGUAGE,
+                hyper::header::CONTENT_LANGUAGE,
+                hyper::header::CONTENT_TYPE,
+                hyper::header::AUTHORIZATION,
+            ],
+            exposed: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allows requests from any origin, with `Access-Control-Allow-Origin: *`.
+    ///
+    /// # Panics
+    ///
+    /// If credentials are allowed, since any website could then make authenticated
+    /// requests on behalf of its visitors. Use [`Cors::allow_origin_fn`] to decide
+    /// which origins can send credentials.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = Origins::Any;
+        self.check();
+        self
+    }
+
+    /// Adds an origin to the allowed ones, e.g. `https://casino.example`.
+    pub fn allow_origin<S: Into<String>>(mut self, origin: S) -> Self {
+        match self.origins {
+            Origins::List(ref mut list) => list.push(origin.into()),
+            _ => self.origins = Origins::List(vec![origin.into()]),
+        }
+
+        self
+    }
+
+    /// Allows the origins for which `predicate` returns `true`.
+    pub fn allow_origin_fn<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.origins = Origins::Predicate(Arc::new(predicate));
+        self
+    }
+
+    /// Replaces the methods allowed in cross-origin requests.
+    pub fn allow_methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Replaces the request headers allowed in cross-origin requests.
+    pub fn allow_headers<I, H>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = H>,
+        H: TryInto<HeaderName>,
+    {
+        self.headers = headers
+            .into_iter()
+            .filter_map(|h| h.try_into().ok())
+            .collect();
+        self
+    }
+
+    /// Sets the response headers the browser will expose to the frontend.
+    pub fn expose_headers<I, H>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = H>,
+        H: TryInto<HeaderName>,
+    {
+        self.exposed = headers
+            .into_iter()
+            .filter_map(|h| h.try_into().ok())
+            .collect();
+        self
+    }
+
+    /// Allows cookies and other credentials to be sent along cross-origin requests.
+    ///
+    /// # Panics
+    ///
+    /// If any origin is allowed, see [`Cors::allow_any_origin`].
+    pub fn allow_credentials(mut self, value: bool) -> Self {
+        self.credentials = value;
+        self.check();
+        self
+    }
+
+    /// Sets how long browsers can cache the result of a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn check(&self) {
+        assert!(
+            !(self.credentials && matches!(self.origins, Origins::Any)),
+            "CORS credentials can't be allowed along any origin, list the origins or use allow_origin_fn"
+        );
+    }
+
+    fn allows_origin(&self, origin: &str) -> bool {
+        match self.origins {
+            Origins::Any => true,
+            Origins::List(ref list) => list.iter().any(|o| o == origin),
+            Origins::Predicate(ref predicate) => predicate(origin),
+        }
+    }
+
+    /// The value of `Access-Control-Allow-Origin` for an allowed origin.
+    fn allow_origin_value(&self, origin: &HeaderValue) -> HeaderValue {
+        match self.origins {
+            Origins::Any => HeaderValue::from_static("*"),
+            _ => origin.clone(),
+        }
+    }
+
+    pub(crate) fn is_preflight(req: &Request) -> bool {
+        req.method() == Method::OPTIONS
+            && req.headers().contains_key(ORIGIN)
+            && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+    }
+
+    /// Answers a preflight request.
+    ///
+    /// The origin related headers are added later by [`Cors::decorate`], like for any other response.
+    pub(crate) fn preflight(&self, req: &Request) -> Result<Response, Error> {
+        let headers = req.headers();
+        let origin = &headers[ORIGIN];
+
+        if !origin.to_str().is_ok_and(|o| self.allows_origin(o)) {
+            return Err(Error::forbidden("Origin not allowed"));
+        }
+
+        let method = headers[ACCESS_CONTROL_REQUEST_METHOD].as_bytes();
+
+        if !self.methods.iter().any(|m| m.as_str().as_bytes() == method) {
+            return Err(Error::forbidden("Method not allowed"));
+        }
+
+        let requested = headers
+            .get(ACCESS_CONTROL_REQUEST_HEADERS)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        for name in requested
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+        {
+            if !self
+                .headers
+                .iter()
+                .any(|h| h.as_str().eq_ignore_ascii_case(name))
+            {
+                return Err(Error::forbidden(format!("Header '{}' not allowed", name)));
+            }
+        }
+
+        let mut res = Response::empty()
+            .status(StatusCode::NO_CONTENT)
+            .header(
+                ACCESS_CONTROL_ALLOW_METHODS,
+                join(&self.methods, |m| m.as_str()),
+            )
+            .header(
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                join(&self.headers, |h| h.as_str()),
+            );
+
+        if let Some(max_age) = self.max_age {
+            res = res.header(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age.as_secs()));
+        }
+
+        Ok(res)
+    }
+
+    /// Adds the CORS headers to a response sent to `origin`, if it's allowed.
+    ///
+    /// Unless any origin is allowed, the response depends on the origin of the request,
+    /// so it varies on it even when there's none, keeping caches from mixing them up.
+    pub(crate) fn decorate(&self, origin: Option<&HeaderValue>, res: &mut Response) {
+        if !matches!(self.origins, Origins::Any) {
+            res.headers_mut()
+                .append(VARY, HeaderValue::from_static("Origin"));
+        }
+
+        let Some(origin) = origin.filter(|o| o.to_str().is_ok_and(|o| self.allows_origin(o)))
+        else {
+            return;
+        };
+
+        let headers = res.headers_mut();
+
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, self.allow_origin_value(origin));
+
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        if !self.exposed.is_empty() {
+            headers.insert(
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                join(&self.exposed, |h| h.as_str()),
+            );
+        }
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Joins a list into a comma separated header value.
+fn join<T>(items: &[T], f: impl Fn(&T) -> &str) -> HeaderValue {
+    let value = items.iter().map(f).collect::<Vec<_>>().join(", ");
+
+    // Methods and header names are always valid header values
+    HeaderValue::from_str(&value).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decorated(cors: &Cors, origin: Option<&'static str>) -> Response {
+        let mut res = Response::empty();
+        cors.decorate(origin.map(HeaderValue::from_static).as_ref(), &mut res);
+        res
+    }
+
+    #[test]
+    #[should_panic]
+    fn refuses_credentials_with_any_origin() {
+        let _ = Cors::new().allow_any_origin().allow_credentials(true);
+    }
+
+    #[test]
+    #[should_panic]
+    fn refuses_any_origin_with_credentials() {
+        let _ = Cors::new().allow_credentials(true).allow_any_origin();
+    }
+
+    #[test]
+    fn echoes_allowed_origins_with_credentials() {
+        let cors = Cors::new()
+            .allow_origin_fn(|o| o.ends_with(".casino.example"))
+            .allow_credentials(true);
+
+        let res = decorated(&cors, Some("https://eu.casino.example"));
+        let headers = res.headers();
+
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://eu.casino.example"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[VARY], "Origin");
+    }
+
+    #[test]
+    fn varies_on_origin_when_restricted() {
+        let cors = Cors::new().allow_origin("https://casino.example");
+
+        for origin in [Some("https://evil.example"), None] {
+            let res = decorated(&cors, origin);
+
+            assert!(!res.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+            assert_eq!(res.headers()[VARY], "Origin");
+        }
+    }
+
+    #[test]
+    fn allows_any_origin_with_a_wildcard() {
+        let res = decorated(&Cors::new().allow_any_origin(), Some("https://a.example"));
+
+        assert_eq!(res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!res.headers().contains_key(VARY));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::response::Response;
+use hyper::{
+    StatusCode,
+    header::{CONTENT_TYPE, HeaderValue},
+};
+use serde::Serialize;
+use std::fmt;
+
+pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An error returned by an [`ApiHandler`](crate::ApiHandler).
+///
+/// Every error carries the status code of the response it will produce,
+/// a machine-readable code and a human-readable message.
+/// [`App`](crate::App) renders it as a JSON body:
+///
+/// ```json
+/// { "code": "invalid_json", "message": "missing field `username` at line 1 column 2" }
+/// ```
+///
+/// Any [`std::error::Error`] converts into an `Error` with status
+/// `500 Internal Server Error`, so `?` can be used freely in handlers.
+/// The original error is kept as the source and is not exposed to the client.
+#[derive(Debug)]
+pub struct Error {
+    status: StatusCode,
+    code: String,
+    message: String,
+    source: Option<BoxError>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: &'a str,
+}
+
+impl Error {
+    pub fn new<C: Into<String>, M: Into<String>>(status: StatusCode, code: C, message: M) -> Self {
+        Self {
+            status,
+            code: code.into(),
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// `400 Bad Request`
+    pub fn bad_request<M: Into<String>>(message: M) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    /// `401 Unauthorized`
+    pub fn unauthorized<M: Into<String>>(message: M) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", message)
+    }
+
+    /// `403 Forbidden`
+    pub fn forbidden<M: Into<String>>(message: M) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "forbidden", message)
+    }
+
+    /// `404 Not Found`
+    pub fn not_found<M: Into<String>>(message: M) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    /// `409 Conflict`
+    pub fn conflict<M: Into<String>>(message: M) -> Self {
+        Self::new(StatusCode::CONFLICT, "conflict", message)
+    }
+
+    /// `500 Internal Server Error`
+    pub fn internal<M: Into<String>>(message: M) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", message)
+    }
+
+    /// Attaches the error that caused this one.
+    pub fn with_source<E: Into<BoxError>>(mut self, source: E) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn source(&self) -> Option<&(dyn std::error::Error + Send + Sync + 'static)> {
+        self.source.as_deref()
+    }
+}
+
+impl<E: std::error::Error + Send + Sync + 'static> From<E> for Error {
+    fn from(err: E) -> Self {
+        Self::internal("Internal server error").with_source(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.status, self.code, self.message)?;
+
+        if let Some(ref source) = self.source {
+            write!(f, ": {}", source)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Error> for Response {
+    fn from(err: Error) -> Self {
+        let body = ErrorBody {
+            code: &err.code,
+            message: &err.message,
+        };
+
+        // Serializing two strings can't fail
+        let json = serde_json::to_string(&body).unwrap_or_default();
+
+        Response::empty()
+            .status(err.status)
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .text(json)
+    }
+}
@@ -0,0 +1,418 @@
+//! Typed handler arguments.
+//!
+//! Any type implementing [`FromRequest`] can be an argument of a function
+//! turned into a handler with [`handler`](crate::handler). Arguments are
+//! extracted in order, and the first one that fails rejects the request
+//! with its error, so the function only runs with valid input.
+//!
+//! Extractors reading the body, [`Json`], [`Form`], [`Payload`], [`Multipart`]
+//! and [`Request`] itself, consume it: they must come last.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use http::{
+//!     Error, Response, Router,
+//!     extract::{Json, Path, Query, State},
+//!     handler,
+//! };
+//! use serde::Deserialize;
+//!
+//! struct Tables;
+//!
+//! #[derive(Deserialize)]
+//! struct Seat {
+//!     room: u32,
+//!     seat: u8,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Options {
+//!     spectate: Option<bool>,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Buyin {
+//!     chips: u64,
+//! }
+//!
+//! async fn sit(
+//!     State(tables): State<Tables>,
+//!     Path(seat): Path<Seat>,
+//!     Query(options): Query<Options>,
+//!     Json(buyin): Json<Buyin>,
+//! ) -> Result<Response, Error> {
+//!     Ok(Response::empty())
+//! }
+//!
+//! let router = Router::new().post("/rooms/:room/seats/:seat", handler(sit));
+//! ```
+
+use crate::{error::Error, format::Format, multipart::Multipart, request::Request};
+use async_trait::async_trait;
+use hyper::StatusCode;
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// Builds a value from the incoming request, rejecting it with an [`Error`] when it can't.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Request, async_trait, extract::FromRequest};
+///
+/// struct Player(String);
+///
+/// #[async_trait]
+/// impl FromRequest for Player {
+///     async fn from_request(req: &mut Request) -> Result<Self, Error> {
+///         req.cookies()
+///             .get("player")
+///             .map(|c| Player(c.value().to_string()))
+///             .ok_or_else(|| Error::unauthorized("Not logged in"))
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait FromRequest: Sized + Send {
+    async fn from_request(req: &mut Request) -> Result<Self, Error>;
+}
+
+/// Makes any extractor optional: a failed extraction yields `None` instead of rejecting.
+#[async_trait]
+impl<T: FromRequest> FromRequest for Option<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        Ok(T::from_request(req).await.ok())
+    }
+}
+
+/// The whole request, for handlers that also need something no extractor provides.
+///
+/// Takes the request, so it must be the last argument.
+#[async_trait]
+impl FromRequest for Request {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        Ok(req.take())
+    }
+}
+
+/// The address of the client, rejecting with `500 Internal Server Error`
+/// for requests that didn't come from a connection.
+#[async_trait]
+impl FromRequest for SocketAddr {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        req.peer_addr()
+            .ok_or_else(|| Error::internal("The request has no peer address"))
+    }
+}
+
+/// Implements `Deref`, `DerefMut` and `into_inner` for a single field wrapper.
+macro_rules! wrapper {
+    ($name:ident) => {
+        impl<T> $name<T> {
+            pub fn into_inner(self) -> T {
+                self.0
+            }
+        }
+
+        impl<T> Deref for $name<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        impl<T> DerefMut for $name<T> {
+            fn deref_mut(&mut self) -> &mut T {
+                &mut self.0
+            }
+        }
+    };
+}
+
+/// The body deserialized from JSON, like [`Request::json`].
+///
+/// Reads the body, so it must be the last argument.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+wrapper!(Json);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromRequest for Json<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        req.take_json().await.map(Json)
+    }
+}
+
+/// An `application/x-www-form-urlencoded` body deserialized, like [`Request::form`].
+///
+/// Reads the body, so it must be the last argument.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Form<T>(pub T);
+
+wrapper!(Form);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromRequest for Form<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        req.take_form().await.map(Form)
+    }
+}
+
+/// The fields of a `multipart/form-data` body, like [`Request::multipart`].
+///
+/// Takes the body, so it must be the last argument.
+#[async_trait]
+impl FromRequest for Multipart {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        req.take_multipart()
+    }
+}
+
+/// The body deserialized in the format given by its `Content-Type`, like [`Request::payload`].
+///
+/// Reads the body, so it must be the last argument.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Payload<T>(pub T);
+
+wrapper!(Payload);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromRequest for Payload<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        req.take_payload().await.map(Payload)
+    }
+}
+
+/// The response format the client prefers, like [`Request::preferred_format`].
+#[async_trait]
+impl FromRequest for Format {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        Ok(req.preferred_format())
+    }
+}
+
+/// The query string deserialized, like [`Request::query`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+wrapper!(Query);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromRequest for Query<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        req.query().map(Query)
+    }
+}
+
+/// The path parameters captured by the [`Router`](crate::Router).
+///
+/// `T` can be a struct or map keyed by parameter name or,
+/// when the route has a single parameter, its plain value.
+/// Rejects with `400 Bad Request` if the parameters don't match `T`.
+///
+/// ```no_run
+/// use http::{Error, Response, extract::Path};
+///
+/// // GET /rooms/:id
+/// async fn room(Path(id): Path<u32>) -> Result<Response, Error> {
+///     Ok(Response::empty())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+wrapper!(Path);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromRequest for Path<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        let params = req.params();
+        let invalid = |e: serde_urlencoded::de::Error| {
+            Error::new(StatusCode::BAD_REQUEST, "invalid_path", e.to_string())
+        };
+
+        // The urlencoded deserializer parses numbers and booleans out of strings
+        let encoded = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params)
+            .finish();
+
+        match serde_urlencoded::from_str::<T>(&encoded) {
+            Ok(value) => Ok(Path(value)),
+
+            Err(_) if params.len() == 1 => {
+                serde_urlencoded::from_str::<HashMap<String, T>>(&encoded)
+                    .map_err(invalid)
+                    .map(|mut map| Path(map.drain().next().unwrap().1))
+            }
+
+            Err(e) => Err(invalid(e)),
+        }
+    }
+}
+
+/// A header that can be extracted with [`Header`].
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{
+///     Error, Response,
+///     extract::{Header, TypedHeader},
+/// };
+/// use std::str::FromStr;
+///
+/// struct ApiKey(String);
+///
+/// impl FromStr for ApiKey {
+///     type Err = std::convert::Infallible;
+///
+///     fn from_str(s: &str) -> Result<Self, Self::Err> {
+///         Ok(ApiKey(s.to_string()))
+///     }
+/// }
+///
+/// impl TypedHeader for ApiKey {
+///     const NAME: &'static str = "x-api-key";
+/// }
+///
+/// async fn admin(Header(key): Header<ApiKey>) -> Result<Response, Error> {
+///     Ok(Response::empty())
+/// }
+/// ```
+pub trait TypedHeader: FromStr {
+    /// The header name, in lowercase.
+    const NAME: &'static str;
+}
+
+/// A header parsed as `T`.
+///
+/// Rejects with `400 Bad Request` if the header is missing or can't be parsed,
+/// use `Option<Header<T>>` for optional headers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Header<T>(pub T);
+
+wrapper!(Header);
+
+#[async_trait]
+impl<T: TypedHeader + Send> FromRequest for Header<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        let value = req.headers().get(T::NAME).ok_or_else(|| {
+            Error::new(
+                StatusCode::BAD_REQUEST,
+                "missing_header",
+                format!("Missing header {}", T::NAME),
+            )
+        })?;
+
+        value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .map(Header)
+            .ok_or_else(|| {
+                Error::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_header",
+                    format!("Invalid header {}", T::NAME),
+                )
+            })
+    }
+}
+
+/// A value registered with [`App::state`](crate::App::state), shared by every request.
+///
+/// Rejects with `500 Internal Server Error` if no value of type `T` was registered.
+#[derive(Debug)]
+pub struct State<T>(pub Arc<T>);
+
+impl<T> Clone for State<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for State<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<T: Send + Sync + 'static> FromRequest for State<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        req.state().map(State).ok_or_else(|| {
+            Error::internal(format!(
+                "No state of type {} registered",
+                std::any::type_name::<T>()
+            ))
+        })
+    }
+}
+
+/// A value stored in the extensions of this request, typically by a middleware.
+///
+/// Unlike [`State`], extensions are scoped to a single request: a middleware
+/// authenticating the client can insert the user it found, for the handler to pick up.
+/// Rejects with `500 Internal Server Error` if no value of type `T` was inserted.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{
+///     Error, Middleware, Next, Request, Response, async_trait, extract::Extension,
+/// };
+///
+/// #[derive(Clone)]
+/// struct Player(String);
+///
+/// struct Auth;
+///
+/// #[async_trait]
+/// impl Middleware for Auth {
+///     async fn handle(&self, mut req: Request, next: Next<'_>) -> Result<Response, Error> {
+///         let player = req
+///             .cookies()
+///             .get("player")
+///             .map(|c| Player(c.value().to_string()))
+///             .ok_or_else(|| Error::unauthorized("Not logged in"))?;
+///
+///         req.extensions_mut().insert(player);
+///         next.run(req).await
+///     }
+/// }
+///
+/// async fn profile(Extension(player): Extension<Player>) -> Result<Response, Error> {
+///     Response::empty().body(player.0)
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Extension<T>(pub T);
+
+wrapper!(Extension);
+
+#[async_trait]
+impl<T: Clone + Send + Sync + 'static> FromRequest for Extension<T> {
+    async fn from_request(req: &mut Request) -> Result<Self, Error> {
+        req.extensions()
+            .get::<T>()
+            .cloned()
+            .map(Extension)
+            .ok_or_else(|| {
+                Error::internal(format!(
+                    "No extension of type {} inserted",
+                    std::any::type_name::<T>()
+                ))
+            })
+    }
+}
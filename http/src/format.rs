@@ -0,0 +1,347 @@
+use crate::error::Error;
+use bytes::Bytes;
+use hyper::{
+    HeaderMap, StatusCode,
+    header::{ACCEPT, CONTENT_TYPE},
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// A serialization format for bodies, picked from the `Accept` header of the
+/// client for responses and from the `Content-Type` header for requests.
+///
+/// JSON is the default, MessagePack and CBOR are compact binary alternatives
+/// for clients that ask for them.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Format, Request, Response};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize)]
+/// struct Bet {
+///     chips: u64,
+/// }
+///
+/// #[derive(Serialize)]
+/// struct Table {
+///     pot: u64,
+/// }
+///
+/// async fn bet(req: Request) -> Result<Response, Error> {
+///     // Read before the body consumes the request
+///     let format = req.preferred_format();
+///     let bet = req.payload::<Bet>().await?;
+///
+///     Response::empty().body_as(format, Table { pot: bet.chips })
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Format {
+    /// `application/json`
+    #[default]
+    Json,
+
+    /// `application/msgpack`
+    MessagePack,
+
+    /// `application/cbor`
+    Cbor,
+}
+
+impl Format {
+    /// Every format, in order of preference when the client likes them equally.
+    pub const ALL: [Format; 3] = [Format::Json, Format::MessagePack, Format::Cbor];
+
+    /// The media type sent in `Content-Type`.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::MessagePack => "application/msgpack",
+            Format::Cbor => "application/cbor",
+        }
+    }
+
+    /// The format of a `Content-Type` value, ignoring its parameters.
+    ///
+    /// Also recognizes `+json` types and the legacy `x-msgpack` and `vnd.msgpack` names.
+    pub fn from_content_type(value: &str) -> Option<Format> {
+        let essence = value.split(';').next().unwrap_or_default().trim();
+        let essence = essence.to_ascii_lowercase();
+
+        match essence.as_str() {
+            "application/json" => Some(Format::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Format::MessagePack)
+            }
+            "application/cbor" => Some(Format::Cbor),
+            _ if essence.starts_with("application/") && essence.ends_with("+json") => {
+                Some(Format::Json)
+            }
+            _ => None,
+        }
+    }
+
+    /// The format the client prefers according to its `Accept` header,
+    /// `None` if it accepts none of them.
+    ///
+    /// Without an `Accept` header any format goes, so JSON is picked.
+    pub fn negotiate(headers: &HeaderMap) -> Option<Format> {
+        if !headers.contains_key(ACCEPT) {
+            return Some(Format::Json);
+        }
+
+        let mut best = None;
+        let mut best_quality = 0.0;
+
+        for format in Self::ALL {
+            let quality = quality(headers, format.content_type());
+
+            if quality > best_quality {
+                best = Some(format);
+                best_quality = quality;
+            }
+        }
+
+        best
+    }
+
+    /// Serializes `payload` in this format.
+    ///
+    /// Fails with `500 Internal Server Error` if the payload can't be serialized.
+    pub fn serialize<T: Serialize>(self, payload: &T) -> Result<Bytes, Error> {
+        let bytes = match self {
+            Format::Json => serde_json::to_vec(payload)?,
+            Format::MessagePack => rmp_serde::to_vec_named(payload)?,
+            Format::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(payload, &mut buf)?;
+                buf
+            }
+        };
+
+        Ok(Bytes::from(bytes))
+    }
+
+    /// Deserializes `bytes` from this format.
+    ///
+    /// Fails with `400 Bad Request` if the bytes don't hold a valid `T`.
+    pub fn deserialize<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, Error> {
+        let result = match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        };
+
+        result.map_err(|message| {
+            let code = match self {
+                Format::Json => "invalid_json",
+                Format::MessagePack => "invalid_msgpack",
+                Format::Cbor => "invalid_cbor",
+            };
+
+            Error::new(StatusCode::BAD_REQUEST, code, message)
+        })
+    }
+}
+
+/// The format of a request body, from its `Content-Type`.
+///
+/// Bodies without a `Content-Type` are assumed to be JSON.
+pub(crate) fn request_format(headers: &HeaderMap) -> Result<Format, Error> {
+    let Some(value) = headers.get(CONTENT_TYPE) else {
+        return Ok(Format::Json);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(Format::from_content_type)
+        .ok_or_else(|| {
+            Error::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Expected a JSON, MessagePack or CBOR body",
+            )
+        })
+}
+
+pub(crate) fn not_acceptable() -> Error {
+    Error::new(
+        StatusCode::NOT_ACCEPTABLE,
+        "not_acceptable",
+        "Expected an Accept header allowing JSON, MessagePack or CBOR",
+    )
+}
+
+/// The quality `Accept` gives to `media_type`, zero if it's not accepted.
+///
+/// The most specific matching range wins, so `application/cbor;q=0` beats `*/*`.
+fn quality(headers: &HeaderMap, media_type: &str) -> f32 {
+    let (kind, _) = media_type.split_once('/').unwrap_or_default();
+
+    // (specificity, quality) of the best match so far
+    let mut matched = (0, 0.0);
+
+    let items = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','));
+
+    for item in items {
+        let mut parts = item.split(';').map(str::trim);
+        let range = parts.next().unwrap_or_default();
+        let quality = parts
+            .find_map(|p| p.strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.parse().ok())
+            .unwrap_or(0.0);
+
+        let specificity = if range.eq_ignore_ascii_case(media_type) {
+            3
+        } else if range
+            .strip_suffix("/*")
+            .is_some_and(|k| k.eq_ignore_ascii_case(kind))
+        {
+            2
+        } else if range == "*/*" {
+            1
+        } else {
+            continue;
+        };
+
+        if specificity > matched.0 {
+            matched = (specificity, quality);
+        }
+    }
+
+    matched.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Request;
+    use http_body_util::Empty;
+    use hyper::header::HeaderValue;
+    use serde::Deserialize;
+
+    fn accepting(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    fn negotiate(value: &'static str) -> Option<Format> {
+        Format::negotiate(&accepting(value))
+    }
+
+    #[test]
+    fn defaults_to_json_without_accept() {
+        assert_eq!(Format::negotiate(&HeaderMap::new()), Some(Format::Json));
+    }
+
+    #[test]
+    fn picks_the_highest_quality() {
+        assert_eq!(negotiate("application/cbor"), Some(Format::Cbor));
+        assert_eq!(
+            negotiate("application/json;q=0.5, application/msgpack"),
+            Some(Format::MessagePack)
+        );
+        assert_eq!(
+            negotiate("application/msgpack;q=0.9, application/cbor;q=0.8"),
+            Some(Format::MessagePack)
+        );
+
+        // Ties go to the order of preference of the server
+        assert_eq!(
+            negotiate("application/cbor, application/msgpack"),
+            Some(Format::MessagePack)
+        );
+    }
+
+    #[test]
+    fn matches_ranges() {
+        assert_eq!(negotiate("*/*"), Some(Format::Json));
+        assert_eq!(negotiate("application/*"), Some(Format::Json));
+        assert_eq!(
+            negotiate("application/*;q=0.5, application/cbor"),
+            Some(Format::Cbor)
+        );
+
+        // The most specific range wins, even when it refuses
+        assert_eq!(
+            negotiate("application/json;q=0, */*"),
+            Some(Format::MessagePack)
+        );
+        assert_eq!(negotiate("application/*;q=0, */*"), None);
+    }
+
+    #[test]
+    fn rejects_clients_accepting_no_format() {
+        assert_eq!(negotiate("text/html"), None);
+        assert_eq!(negotiate("text/*, image/png"), None);
+        assert_eq!(negotiate("*/*;q=0"), None);
+
+        let req = hyper::Request::builder()
+            .header(ACCEPT, "text/html")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let req = Request::from(req);
+
+        assert_eq!(req.preferred_format(), Format::Json);
+        assert_eq!(
+            req.accepted_format().unwrap_err().status(),
+            StatusCode::NOT_ACCEPTABLE
+        );
+    }
+
+    #[test]
+    fn reads_content_types() {
+        let cases = [
+            ("application/json; charset=utf-8", Some(Format::Json)),
+            ("Application/JSON", Some(Format::Json)),
+            ("application/problem+json", Some(Format::Json)),
+            ("application/x-msgpack", Some(Format::MessagePack)),
+            ("application/vnd.msgpack", Some(Format::MessagePack)),
+            ("application/cbor", Some(Format::Cbor)),
+            ("text/plain", None),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(Format::from_content_type(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_request_bodies() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_format(&headers).unwrap(), Format::Json);
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let err = request_format(&headers).unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn round_trips_payloads() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Table {
+            pot: u64,
+            players: Vec<String>,
+        }
+
+        let table = Table {
+            pot: 1200,
+            players: vec!["ann".to_string(), "bob".to_string()],
+        };
+
+        for format in Format::ALL {
+            let bytes = format.serialize(&table).unwrap();
+            assert_eq!(format.deserialize::<Table>(&bytes).unwrap(), table);
+
+            let err = format.deserialize::<Table>(b"\xff\x00").unwrap_err();
+            assert_eq!(err.status(), StatusCode::BAD_REQUEST, "{:?}", format);
+        }
+    }
+}
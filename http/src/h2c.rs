@@ -0,0 +1,402 @@
+//! The HTTP/1.1 `Upgrade: h2c` mechanism, switching a cleartext connection to HTTP/2.
+//!
+//! The request asking for the upgrade is answered as the first HTTP/2 stream,
+//! which hyper can't take over from an HTTP/1 connection. Instead, once the
+//! client has sent its connection preface, the request is replayed to the HTTP/2
+//! server as a `HEADERS` frame on stream 1, as if the client had sent it.
+
+use crate::response::Response;
+use bytes::{BufMut, Bytes, BytesMut};
+use hyper::{
+    Request, StatusCode,
+    header::{CONNECTION, CONTENT_LENGTH, HOST, HeaderValue, TE, TRANSFER_ENCODING, UPGRADE},
+};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const HTTP2_SETTINGS: &str = "http2-settings";
+
+/// What the client sends first over HTTP/2, before its `SETTINGS` frame.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Size of the header of every HTTP/2 frame.
+const FRAME_HEADER_LEN: usize = 9;
+
+/// Largest frame payload peers must accept before settings say otherwise.
+const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_SETTINGS: u8 = 0x4;
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+
+/// Headers that only make sense for the HTTP/1.1 connection, not sent over HTTP/2.
+const CONNECTION_HEADERS: [&str; 4] = ["keep-alive", "proxy-connection", HTTP2_SETTINGS, "upgrade"];
+
+/// The `HEADERS` frame replaying `req` on stream 1, if it's an upgrade to h2c that can be honored.
+///
+/// Requests with a body keep being served over HTTP/1.1, as the upgrade is optional for the server.
+pub(crate) fn upgrade<B>(req: &Request<B>) -> Option<Bytes> {
+    let headers = req.headers();
+
+    let lists = |name, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    };
+
+    let upgrade = lists(UPGRADE, "h2c")
+        && lists(CONNECTION, "upgrade")
+        && lists(CONNECTION, HTTP2_SETTINGS)
+        && headers.get_all(HTTP2_SETTINGS).iter().count() == 1;
+
+    let bodyless = !headers.contains_key(TRANSFER_ENCODING)
+        && headers
+            .get(CONTENT_LENGTH)
+            .is_none_or(|len| len.as_bytes() == b"0");
+
+    if !upgrade || !bodyless {
+        return None;
+    }
+
+    headers_frame(req)
+}
+
+/// The `101 Switching Protocols` answer to an upgrade request.
+pub(crate) fn switching_protocols() -> Response {
+    Response::empty()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(CONNECTION, HeaderValue::from_static("upgrade"))
+        .header(UPGRADE, HeaderValue::from_static("h2c"))
+}
+
+/// Encodes the head of `req` as a `HEADERS` frame ending stream 1.
+///
+/// Fields are literals that aren't indexed, so the HPACK state of the
+/// connection is left as the client set it up.
+fn headers_frame<B>(req: &Request<B>) -> Option<Bytes> {
+    let headers = req.headers();
+
+    let authority = headers
+        .get(HOST)
+        .map(HeaderValue::as_bytes)
+        .or(req.uri().authority().map(|a| a.as_str().as_bytes()))
+        .unwrap_or_default();
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |p| p.as_str())
+        .as_bytes();
+
+    // Headers named by `Connection` are hop-by-hop as well
+    let hop_by_hop = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+
+    let mut block = BytesMut::new();
+    literal(&mut block, b":method", req.method().as_str().as_bytes());
+    literal(&mut block, b":scheme", b"http");
+    literal(&mut block, b":path", path);
+    literal(&mut block, b":authority", authority);
+
+    for (name, value) in headers {
+        let name = name.as_str();
+
+        let skipped = name == HOST.as_str()
+            || name == CONNECTION.as_str()
+            || name == TRANSFER_ENCODING.as_str()
+            || (name == TE.as_str() && value.as_bytes() != b"trailers")
+            || CONNECTION_HEADERS.contains(&name)
+            || hop_by_hop.iter().any(|h| h == name);
+
+        if !skipped {
+            literal(&mut block, name.as_bytes(), value.as_bytes());
+        }
+    }
+
+    if block.len() > DEFAULT_MAX_FRAME_SIZE {
+        return None;
+    }
+
+    let mut frame = BytesMut::with_capacity(FRAME_HEADER_LEN + block.len());
+    frame.put_uint(block.len() as u64, 3);
+    frame.put_u8(FRAME_HEADERS);
+    frame.put_u8(FLAG_END_STREAM | FLAG_END_HEADERS);
+    frame.put_u32(1);
+    frame.put_slice(&block);
+
+    Some(frame.freeze())
+}
+
+/// Appends a literal header field without indexing, with a new name.
+fn literal(block: &mut BytesMut, name: &[u8], value: &[u8]) {
+    block.put_u8(0);
+    string(block, name);
+    string(block, value);
+}
+
+/// Appends a string that isn't Huffman encoded, prefixed by its length.
+fn string(block: &mut BytesMut, value: &[u8]) {
+    integer(block, value.len(), 7);
+    block.put_slice(value);
+}
+
+/// Appends an integer with an `n` bits prefix, as specified by HPACK.
+fn integer(block: &mut BytesMut, mut value: usize, n: u8) {
+    let max = (1 << n) - 1;
+
+    if value < max {
+        block.put_u8(value as u8);
+        return;
+    }
+
+    block.put_u8(max as u8);
+    value -= max;
+
+    while value >= 128 {
+        block.put_u8((value % 128 + 128) as u8);
+        value /= 128;
+    }
+
+    block.put_u8(value as u8);
+}
+
+/// An upgraded connection that slips the replayed request in right after
+/// the preface and first `SETTINGS` frame of the client.
+pub(crate) struct Upgraded<S> {
+    inner: S,
+    state: State,
+}
+
+enum State {
+    /// Reading the preface and first frame, with the request to insert after them
+    Head {
+        read: BytesMut,
+        stream_one: Bytes,
+    },
+
+    /// Handing out the head and the request before reading further
+    Replay(Bytes),
+
+    Passthrough,
+}
+
+impl<S> Upgraded<S> {
+    pub(crate) fn new(inner: S, stream_one: Bytes) -> Self {
+        Self {
+            inner,
+            state: State::Head {
+                read: BytesMut::new(),
+                stream_one,
+            },
+        }
+    }
+}
+
+/// The length of the preface and first frame in `read`, once they've been fully read.
+///
+/// A first frame that isn't a valid `SETTINGS` one is a protocol error the HTTP/2 server
+/// will report, so the bytes are passed on as they are.
+fn head_len(read: &[u8]) -> Option<Result<usize, ()>> {
+    let checked = read.len().min(PREFACE.len());
+
+    if read[..checked] != PREFACE[..checked] {
+        return Some(Err(()));
+    }
+
+    let header = read.get(PREFACE.len()..PREFACE.len() + FRAME_HEADER_LEN)?;
+
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+
+    if header[3] != FRAME_SETTINGS || len > DEFAULT_MAX_FRAME_SIZE {
+        return Some(Err(()));
+    }
+
+    let total = PREFACE.len() + FRAME_HEADER_LEN + len;
+
+    (read.len() >= total).then_some(Ok(total))
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Upgraded<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.state {
+                State::Passthrough => return Pin::new(&mut this.inner).poll_read(cx, buf),
+
+                State::Replay(ref mut pending) => {
+                    let len = pending.len().min(buf.remaining());
+                    buf.put_slice(&pending.split_to(len));
+
+                    if pending.is_empty() {
+                        this.state = State::Passthrough;
+                    }
+
+                    return Poll::Ready(Ok(()));
+                }
+
+                State::Head {
+                    ref mut read,
+                    ref stream_one,
+                } => {
+                    match head_len(read) {
+                        Some(Ok(len)) => {
+                            let rest = read.split_off(len);
+                            read.extend_from_slice(stream_one);
+                            read.extend_from_slice(&rest);
+
+                            this.state = State::Replay(read.split().freeze());
+                            continue;
+                        }
+                        Some(Err(())) => {
+                            this.state = State::Replay(read.split().freeze());
+                            continue;
+                        }
+                        None => {}
+                    }
+
+                    let mut chunk = [0; 4096];
+                    let mut chunk = ReadBuf::new(&mut chunk);
+
+                    match Pin::new(&mut this.inner).poll_read(cx, &mut chunk) {
+                        Poll::Ready(Ok(())) if chunk.filled().is_empty() => {
+                            // Closed before the end of the head, pass on what came
+                            this.state = State::Replay(read.split().freeze());
+                        }
+                        Poll::Ready(Ok(())) => read.extend_from_slice(chunk.filled()),
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Upgraded<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    fn request(headers: &[(&str, &str)]) -> Request<()> {
+        let mut req = Request::builder().uri("/tables?open=1");
+
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+
+        req.body(()).unwrap()
+    }
+
+    const UPGRADE_HEADERS: [(&str, &str); 4] = [
+        ("host", "casino.test"),
+        ("connection", "Upgrade, HTTP2-Settings"),
+        ("upgrade", "h2c"),
+        ("http2-settings", "AAMAAABkAAQAoAAAAAIAAAAA"),
+    ];
+
+    #[test]
+    fn detects_upgrades() {
+        assert!(upgrade(&request(&UPGRADE_HEADERS)).is_some());
+        assert!(upgrade(&request(&UPGRADE_HEADERS[..3])).is_none());
+        assert!(upgrade(&request(&[("upgrade", "websocket")])).is_none());
+
+        let with_body = [UPGRADE_HEADERS.as_slice(), &[("content-length", "3")]].concat();
+        assert!(upgrade(&request(&with_body)).is_none());
+    }
+
+    #[test]
+    fn encodes_the_request_on_stream_one() {
+        let frame = upgrade(&request(&UPGRADE_HEADERS)).unwrap();
+        let block = &frame[FRAME_HEADER_LEN..];
+
+        assert_eq!(&frame[..3], &(block.len() as u32).to_be_bytes()[1..]);
+        assert_eq!(frame[3], FRAME_HEADERS);
+        assert_eq!(frame[4], FLAG_END_STREAM | FLAG_END_HEADERS);
+        assert_eq!(&frame[5..9], &[0, 0, 0, 1]);
+
+        let mut expected = BytesMut::new();
+        literal(&mut expected, b":method", b"GET");
+        literal(&mut expected, b":scheme", b"http");
+        literal(&mut expected, b":path", b"/tables?open=1");
+        literal(&mut expected, b":authority", b"casino.test");
+        assert_eq!(block, &expected[..]);
+    }
+
+    #[test]
+    fn encodes_long_integers() {
+        let mut block = BytesMut::new();
+        integer(&mut block, 1337, 5);
+        assert_eq!(&block[..], &[31, 154, 10]);
+    }
+
+    #[tokio::test]
+    async fn replays_the_request_after_the_settings() {
+        let settings = [0, 0, 6, FRAME_SETTINGS, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 100];
+        let client = [PREFACE, &settings, b"rest"].concat();
+
+        let mut upgraded = Upgraded::new(&client[..], Bytes::from_static(b"stream one"));
+        let mut read = Vec::new();
+        upgraded.read_to_end(&mut read).await.unwrap();
+
+        assert_eq!(read, [PREFACE, &settings, b"stream one", b"rest"].concat());
+    }
+
+    #[tokio::test]
+    async fn passes_other_bytes_on() {
+        let client = b"GET / HTTP/1.1\r\n\r\n";
+
+        let mut upgraded = Upgraded::new(&client[..], Bytes::from_static(b"stream one"));
+        let mut read = Vec::new();
+        upgraded.read_to_end(&mut read).await.unwrap();
+
+        assert_eq!(read, client);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{
+    error::Error, extract::FromRequest, request::Request, response::Response, traits::ApiHandler,
+};
+use async_trait::async_trait;
+use std::marker::PhantomData;
+
+/// An async function taking [extractors](crate::extract) as arguments, see [`handler`].
+pub struct Handler<F, Args> {
+    f: F,
+    args: PhantomData<fn() -> Args>,
+}
+
+/// Turns an async function whose arguments all implement
+/// [`FromRequest`] into an [`ApiHandler`], up to 8 arguments.
+///
+/// Arguments are extracted in order before the function is called.
+/// If one fails, its error is returned and the function isn't called.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Response, Router, extract::Path, handler};
+///
+/// async fn room(Path(id): Path<u32>) -> Result<Response, Error> {
+///     Response::empty().body(format!("room {id}"))
+/// }
+///
+/// let router = Router::new().get("/rooms/:id", handler(room));
+/// ```
+pub fn handler<F, Args>(f: F) -> Handler<F, Args> {
+    Handler {
+        f,
+        args: PhantomData,
+    }
+}
+
+macro_rules! impl_handler {
+    ($($arg:ident),*) => {
+        #[async_trait]
+        impl<F, Fut, $($arg,)*> ApiHandler for Handler<F, ($($arg,)*)>
+        where
+            F: Fn($($arg),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Result<Response, Error>> + Send,
+            $($arg: FromRequest + 'static,)*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            async fn incoming(&self, mut req: Request) -> Result<Response, Error> {
+                $(let $arg = $arg::from_request(&mut req).await?;)*
+                (self.f)($($arg),*).await
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(T1);
+impl_handler!(T1, T2);
+impl_handler!(T1, T2, T3);
+impl_handler!(T1, T2, T3, T4);
+impl_handler!(T1, T2, T3, T4, T5);
+impl_handler!(T1, T2, T3, T4, T5, T6);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7, T8);
@@ -0,0 +1,143 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Instant,
+};
+
+/// Wraps a connection, recording when bytes last went through it.
+pub(crate) struct Tracked<S> {
+    inner: S,
+    activity: Activity,
+}
+
+/// When a [`Tracked`] connection was last used, readable from another task.
+#[derive(Clone)]
+pub(crate) struct Activity {
+    opened: Instant,
+
+    /// Milliseconds since `opened`
+    last: Arc<AtomicU64>,
+}
+
+impl Activity {
+    fn touch(&self) {
+        let elapsed = self.opened.elapsed().as_millis() as u64;
+        self.last.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// The instant at which the connection will have been idle for `timeout`,
+    /// if nothing goes through it in the meantime.
+    pub(crate) fn deadline(&self, timeout: Duration) -> Instant {
+        let last = Duration::from_millis(self.last.load(Ordering::Relaxed));
+        self.opened + last + timeout
+    }
+}
+
+impl<S> Tracked<S> {
+    pub(crate) fn new(inner: S) -> (Self, Activity) {
+        let activity = Activity {
+            opened: Instant::now(),
+            last: Arc::new(AtomicU64::new(0)),
+        };
+
+        let tracked = Self {
+            inner,
+            activity: activity.clone(),
+        };
+
+        (tracked, activity)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Tracked<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if buf.filled().len() > before {
+            self.activity.touch();
+        }
+
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Tracked<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = poll
+            && n > 0
+        {
+            self.activity.touch();
+        }
+
+        poll
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+
+        if let Poll::Ready(Ok(n)) = poll
+            && n > 0
+        {
+            self.activity.touch();
+        }
+
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn moves_the_deadline_with_traffic() {
+        let (client, _server) = tokio::io::duplex(64);
+        let (mut tracked, activity) = Tracked::new(client);
+
+        let timeout = Duration::from_secs(1);
+        let opened = activity.deadline(timeout);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(activity.deadline(timeout), opened);
+
+        tracked.write_all(b"ping").await.unwrap();
+        assert!(activity.deadline(timeout) >= opened + Duration::from_millis(20));
+    }
+}
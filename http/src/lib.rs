@@ -1,50 +1,57 @@
-use hyper::{server::conn::http1, service::service_fn};
-use hyper_util::rt::TokioIo;
-use std::{net::SocketAddr, sync::Arc};
-use tokio::net::TcpListener;
-
+mod access_log;
+mod app;
+mod body;
+mod compression;
+mod conditional;
+pub mod cookie;
+mod cors;
+mod error;
+pub mod extract;
+mod format;
+mod h2c;
+mod handler;
+mod idle;
+mod listener;
+pub mod metrics;
+mod middleware;
+mod multipart;
+mod rate_limit;
 mod request;
 mod response;
+mod router;
+mod sse;
+mod state;
+mod static_files;
+pub mod testing;
+mod tls;
 mod traits;
+mod websocket;
 
+pub use app::{
+    App, DEFAULT_HEADER_READ_TIMEOUT, DEFAULT_IDLE_TIMEOUT, DEFAULT_SHUTDOWN_TIMEOUT, Protocol,
+    ShutdownReport,
+};
 pub use async_trait::async_trait;
-pub use request::Request;
-pub use response::Response;
-pub use traits::ApiHandler;
-
-pub struct App {
-    listener: TcpListener,
-}
-
-impl App {
-    pub async fn new(addr: SocketAddr) -> tokio::io::Result<Self> {
-        let listener = TcpListener::bind(addr).await?;
-        Ok(Self { listener })
-    }
-
-    pub async fn run<H: ApiHandler>(self, handler: H) -> tokio::io::Result<()> {
-        let handler = Arc::new(handler);
-
-        loop {
-            let (stream, _) = self.listener.accept().await?;
-            let io = TokioIo::new(stream);
-            let handler = handler.clone();
-
-            let service = service_fn(move |req| {
-                let handler = handler.clone();
-                async move {
-                    match handler.incoming(req.into()).await {
-                        Ok(response) => Ok::<_, hyper::Error>(response.into()),
-                        Err(err) => Err(err),
-                    }
-                }
-            });
-
-            tokio::spawn(async move {
-                if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
-                    eprintln!("Error serving connection: {:?}", e);
-                };
-            });
-        }
-    }
-}
+pub use body::{BodyLimit, Chunks, DEFAULT_BODY_LIMIT, RequestBody};
+pub use compression::{Compression, DEFAULT_MIN_COMPRESS_SIZE};
+pub use conditional::{CacheControl, ETag};
+pub use cors::Cors;
+pub use error::Error;
+pub use format::Format;
+pub use handler::{Handler, handler};
+pub use listener::Listener;
+pub use metrics::{DEFAULT_METRICS_PATH, Metrics};
+pub use middleware::Next;
+pub use multipart::{DEFAULT_SPOOL_THRESHOLD, Field, Multipart, Spooled};
+pub use rate_limit::{DEFAULT_RATE_LIMIT_KEYS, RateLimit, RateLimited};
+pub use request::{REQUEST_ID_HEADER, Request};
+pub use response::{Body, Response};
+pub use router::Router;
+pub use sse::{Event, Sse};
+pub use static_files::StaticFiles;
+pub use tls::Tls;
+pub use traits::{ApiHandler, Middleware};
+pub use websocket::{
+    CloseFrame, Message, WebSocket, WebSocketError, WebSocketReceiver, WebSocketSender,
+    WebSocketUpgrade,
+};
@@ -0,0 +1,300 @@
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(unix)]
+use std::{
+    env,
+    os::{
+        fd::{FromRawFd, OwnedFd, RawFd},
+        unix::fs::FileTypeExt,
+    },
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+};
+
+/// First file descriptor passed by socket activation, after stdin, stdout and stderr.
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Connections waiting to be accepted before new ones are refused, like the default of Tokio.
+const BACKLOG: i32 = 1024;
+
+/// Whether the sockets passed by socket activation have already been taken.
+#[cfg(unix)]
+static ACTIVATED: AtomicBool = AtomicBool::new(false);
+
+/// A socket an [`App`](crate::App) accepts connections on.
+///
+/// Unix domain sockets and socket activation are only available on Unix.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{App, Listener};
+///
+/// #[tokio::main]
+/// async fn main() -> tokio::io::Result<()> {
+///     // The public API on both IP versions, and a local admin socket
+///     let app = App::from_listeners([
+///         Listener::bind("0.0.0.0:8080".parse().unwrap()).await?,
+///         Listener::bind("[::]:8080".parse().unwrap()).await?,
+///         Listener::bind_unix("/run/casino/admin.sock")?,
+///     ]);
+///
+///     Ok(())
+/// }
+/// ```
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix {
+        listener: UnixListener,
+
+        /// The socket file created by [`Listener::bind_unix`], removed when dropped
+        path: Option<PathBuf>,
+    },
+}
+
+impl Listener {
+    /// Binds a TCP socket to `addr`.
+    ///
+    /// IPv6 sockets only accept IPv6 clients, so that `[::]` and `0.0.0.0`
+    /// can both be bound on the same port to serve both versions.
+    pub async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+        if addr.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(BACKLOG)?;
+
+        TcpListener::from_std(socket.into()).map(Listener::Tcp)
+    }
+
+    /// Binds a Unix domain socket at `path`, removing the file when the listener is dropped.
+    ///
+    /// A socket file left over at `path`, e.g. by a crashed process, is replaced.
+    /// Any other kind of file makes binding fail.
+    #[cfg(unix)]
+    pub fn bind_unix<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(Listener::Unix {
+            listener: UnixListener::bind(path)?,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Takes the sockets passed by a supervisor through socket activation
+    /// (`LISTEN_PID` and `LISTEN_FDS`, as done by systemd), in order.
+    ///
+    /// Returns no listener if the process wasn't socket activated,
+    /// or if they have already been taken by a previous call.
+    #[cfg(unix)]
+    pub fn from_env() -> io::Result<Vec<Listener>> {
+        let pid = env::var("LISTEN_PID")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok());
+
+        // The variables may have been inherited from a parent, meant for it
+        if pid != Some(std::process::id()) {
+            return Ok(Vec::new());
+        }
+
+        let count = env::var("LISTEN_FDS")
+            .ok()
+            .and_then(|v| v.parse::<RawFd>().ok())
+            .unwrap_or_default();
+
+        if ACTIVATED.swap(true, Ordering::SeqCst) {
+            return Ok(Vec::new());
+        }
+
+        (LISTEN_FDS_START..LISTEN_FDS_START + count)
+            .map(|fd| {
+                // Safety: the supervisor hands these descriptors over to this process
+                let socket = unsafe { Socket::from_raw_fd(fd) };
+                Self::from_socket(socket)
+            })
+            .collect()
+    }
+
+    /// Wraps a listening socket, whether it's a Unix or TCP one.
+    #[cfg(unix)]
+    fn from_socket(socket: Socket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+
+        match socket.local_addr()?.domain() {
+            Domain::UNIX => Ok(Listener::Unix {
+                listener: UnixListener::from_std(OwnedFd::from(socket).into())?,
+                path: None,
+            }),
+            _ => TcpListener::from_std(socket.into()).map(Listener::Tcp),
+        }
+    }
+
+    /// The address of a TCP listener, useful after binding port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Listener::Unix { .. } => None,
+        }
+    }
+
+    /// Accepts a connection, with the address of the client for TCP ones.
+    pub(crate) fn poll_accept(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(Stream, Option<SocketAddr>)>> {
+        match self {
+            Listener::Tcp(listener) => listener
+                .poll_accept(cx)
+                .map_ok(|(stream, peer)| (Stream::Tcp(stream), Some(peer))),
+            #[cfg(unix)]
+            Listener::Unix { listener, .. } => listener
+                .poll_accept(cx)
+                .map_ok(|(stream, _)| (Stream::Unix(stream), None)),
+        }
+    }
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Listener::Tcp(listener)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixListener> for Listener {
+    fn from(listener: UnixListener) -> Self {
+        Listener::Unix {
+            listener,
+            path: None,
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{}", addr),
+                Err(_) => write!(f, "tcp socket"),
+            },
+            #[cfg(unix)]
+            Listener::Unix { listener, path } => {
+                let addr = listener.local_addr().ok();
+
+                match path
+                    .as_deref()
+                    .or(addr.as_ref().and_then(|a| a.as_pathname()))
+                {
+                    Some(path) => write!(f, "unix:{}", path.display()),
+                    None => write!(f, "unix socket"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix {
+            path: Some(path), ..
+        } = self
+        {
+            _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A connection accepted by a [`Listener`].
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Stream::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
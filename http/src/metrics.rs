@@ -0,0 +1,302 @@
+use crate::{
+    error::Error, request::Request, response::Response, router::MatchedRoute, traits::ApiHandler,
+};
+use async_trait::async_trait;
+use hyper::{
+    Method, StatusCode,
+    header::{CONTENT_TYPE, HeaderValue},
+};
+use prometheus_client::{
+    encoding::{EncodeLabelSet, text::encode},
+    metrics::{counter::Counter, family::Family, gauge::Gauge, histogram::Histogram},
+    registry::{Metric, Registry, Unit},
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub use prometheus_client;
+
+/// Path metrics are served on by default.
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+
+/// Upper bounds of the latency buckets, in seconds, the same as other Prometheus clients.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Route label of requests no route matched, including those handled by a fallback.
+const UNMATCHED: &str = "unmatched";
+
+/// Method label of requests with an extension method, which clients can make up at will.
+const OTHER_METHOD: &str = "OTHER";
+
+const CONTENT_TYPE_OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RequestLabels {
+    method: String,
+    route: String,
+    status: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RouteLabels {
+    method: String,
+    route: String,
+}
+
+/// Prometheus metrics of an [`App`](crate::App), enabled with [`App::metrics`](crate::App::metrics).
+///
+/// Records, in the text exposition format:
+/// - `http_requests_total`, by method, route pattern and status class (`2xx`, `4xx`...),
+///   where non-standard methods are all labelled `OTHER`
+/// - `http_request_duration_seconds`, a histogram of the time until the response
+///   is ready, by method and route pattern
+/// - `http_requests_in_flight`, the requests being handled
+/// - `http_connections_open`, the connections being served
+///
+/// Routes are labelled by the pattern they were registered with, like `/rooms/:id`,
+/// so the number of series doesn't grow with the paths clients send.
+///
+/// The metrics are served on [`DEFAULT_METRICS_PATH`] by every listener of the app.
+/// Anyone reaching the app can read them, so either change the [path](Metrics::path),
+/// or serve them only on an internal listener by mounting the `Metrics` as a handler.
+///
+/// Other metrics, such as game counters, can be added to the same [registry](Metrics::register).
+/// Cloning a `Metrics` gives a handle to the same registry.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{
+///     App, Metrics, Router,
+///     metrics::prometheus_client::metrics::counter::Counter,
+/// };
+///
+/// #[tokio::main]
+/// async fn main() -> tokio::io::Result<()> {
+///     let metrics = Metrics::new();
+///
+///     let hands = Counter::<u64>::default();
+///     metrics.register("casino_hands_dealt", "Hands dealt since start", hands.clone());
+///
+///     App::new("127.0.0.1:5050".parse().unwrap())
+///         .await?
+///         .metrics(metrics)
+///         .run(Router::new())
+///         .await?;
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Metrics {
+    path: Option<String>,
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    registry: Mutex<Registry>,
+    requests: Family<RequestLabels, Counter>,
+    latency: Family<RouteLabels, Histogram>,
+    in_flight: Gauge,
+    connections: Gauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let requests = Family::<RequestLabels, Counter>::default();
+        let latency = Family::<RouteLabels, Histogram>::new_with_constructor(|| {
+            Histogram::new(LATENCY_BUCKETS)
+        });
+        let in_flight = Gauge::default();
+        let connections = Gauge::default();
+
+        let mut registry = Registry::default();
+
+        registry.register("http_requests", "HTTP requests handled", requests.clone());
+        registry.register_with_unit(
+            "http_request_duration",
+            "Time until the response of HTTP requests is ready",
+            Unit::Seconds,
+            latency.clone(),
+        );
+        registry.register(
+            "http_requests_in_flight",
+            "HTTP requests being handled",
+            in_flight.clone(),
+        );
+        registry.register(
+            "http_connections_open",
+            "Connections being served",
+            connections.clone(),
+        );
+
+        Self {
+            path: Some(DEFAULT_METRICS_PATH.to_string()),
+            inner: Arc::new(Inner {
+                registry: Mutex::new(registry),
+                requests,
+                latency,
+                in_flight,
+                connections,
+            }),
+        }
+    }
+
+    /// Sets the path the [`App`](crate::App) serves the metrics on,
+    /// [`DEFAULT_METRICS_PATH`] by default. `None` only records them.
+    pub fn path<'a, P: Into<Option<&'a str>>>(mut self, path: P) -> Self {
+        self.path = path.into().map(str::to_string);
+        self
+    }
+
+    /// Adds a metric to the registry, exposed along the HTTP ones.
+    ///
+    /// Keep a clone of `metric` to update it, they share the same value.
+    /// Counters get a `_total` suffix.
+    pub fn register<N: Into<String>, H: Into<String>>(
+        &self,
+        name: N,
+        help: H,
+        metric: impl Metric,
+    ) {
+        self.inner
+            .registry
+            .lock()
+            .unwrap()
+            .register(name, help, metric);
+    }
+
+    /// Renders every metric in the OpenMetrics text format, which Prometheus scrapes.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        // Writing to a String can't fail
+        _ = encode(&mut out, &self.inner.registry.lock().unwrap());
+        out
+    }
+
+    /// Whether `req` asks for the metrics on the configured path.
+    pub(crate) fn is_scrape(&self, req: &Request) -> bool {
+        self.path
+            .as_deref()
+            .is_some_and(|path| req.method() == Method::GET && req.uri().path() == path)
+    }
+
+    /// Counts a request as being handled until the returned guard is dropped.
+    pub(crate) fn in_flight(&self) -> InFlight {
+        self.inner.in_flight.inc();
+        InFlight(self.inner.in_flight.clone())
+    }
+
+    /// Counts a connection as open until the returned guard is dropped.
+    pub(crate) fn connection(&self) -> InFlight {
+        self.inner.connections.inc();
+        InFlight(self.inner.connections.clone())
+    }
+
+    pub(crate) fn record(
+        &self,
+        method: &Method,
+        route: &MatchedRoute,
+        status: StatusCode,
+        latency: Duration,
+    ) {
+        let route = route.get().unwrap_or_else(|| UNMATCHED.to_string());
+        let method = method_label(method);
+
+        self.inner
+            .requests
+            .get_or_create(&RequestLabels {
+                method: method.to_string(),
+                route: route.clone(),
+                status: format!("{}xx", status.as_u16() / 100),
+            })
+            .inc();
+
+        self.inner
+            .latency
+            .get_or_create(&RouteLabels {
+                method: method.to_string(),
+                route,
+            })
+            .observe(latency.as_secs_f64());
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serves the metrics, for mounting them on a route of another app or listener.
+#[async_trait]
+impl ApiHandler for Metrics {
+    async fn incoming(&self, _: Request) -> Result<Response, Error> {
+        Ok(Response::empty()
+            .header(
+                CONTENT_TYPE,
+                HeaderValue::from_static(CONTENT_TYPE_OPENMETRICS),
+            )
+            .text(self.render()))
+    }
+}
+
+/// The label of `method`, folding extension methods into one so that
+/// clients can't create new series with every request.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => OTHER_METHOD,
+    }
+}
+
+/// Decrements a gauge when dropped.
+pub(crate) struct InFlight(Gauge);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_extension_methods() {
+        let metrics = Metrics::new();
+        let route = MatchedRoute::default();
+
+        for method in ["BREW", "WHEN", "GET"] {
+            let method = Method::from_bytes(method.as_bytes()).unwrap();
+            metrics.record(&method, &route, StatusCode::OK, Duration::ZERO);
+        }
+
+        let rendered = metrics.render();
+
+        assert!(
+            rendered.contains(
+                r#"http_requests_total{method="OTHER",route="unmatched",status="2xx"} 2"#
+            )
+        );
+        assert!(
+            rendered
+                .contains(r#"http_requests_total{method="GET",route="unmatched",status="2xx"} 1"#)
+        );
+        assert!(!rendered.contains("BREW"));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{
+    error::Error,
+    request::Request,
+    response::Response,
+    traits::{ApiHandler, Middleware},
+};
+use async_trait::async_trait;
+
+/// The remaining part of a middleware chain.
+///
+/// Calling [`Next::run`] passes the request to the next middleware,
+/// or to the handler once every middleware has been visited.
+pub struct Next<'a> {
+    layers: &'a [Box<dyn Middleware>],
+    handler: &'a dyn ApiHandler,
+}
+
+impl Next<'_> {
+    pub async fn run(self, req: Request) -> Result<Response, Error> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => {
+                let next = Next {
+                    layers,
+                    handler: self.handler,
+                };
+
+                layer.handle(req, next).await
+            }
+
+            None => self.handler.incoming(req).await,
+        }
+    }
+}
+
+/// A handler wrapped by an ordered list of middlewares,
+/// the first one being the outermost.
+pub(crate) struct Stack<H> {
+    layers: Vec<Box<dyn Middleware>>,
+    handler: H,
+}
+
+impl<H: ApiHandler> Stack<H> {
+    pub(crate) fn new(layers: Vec<Box<dyn Middleware>>, handler: H) -> Self {
+        Self { layers, handler }
+    }
+}
+
+#[async_trait]
+impl<H: ApiHandler> ApiHandler for Stack<H> {
+    async fn incoming(&self, req: Request) -> Result<Response, Error> {
+        let next = Next {
+            layers: &self.layers,
+            handler: &self.handler,
+        };
+
+        next.run(req).await
+    }
+}
@@ -0,0 +1,490 @@
+use crate::{body::Chunks, error::Error};
+use bytes::{Bytes, BytesMut};
+use hyper::StatusCode;
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+use tempfile::TempPath;
+use tokio::{fs::File, io::AsyncWriteExt, task::spawn_blocking};
+
+/// Size above which a [spooled](Field::spool) part goes to a temporary file, 256 KiB by default.
+pub const DEFAULT_SPOOL_THRESHOLD: usize = 256 * 1024;
+
+/// A streaming `multipart/form-data` body, obtained with [`Request::multipart`](crate::Request::multipart).
+///
+/// Fields are read one at a time, in the order the client sent them,
+/// without buffering the whole body. Besides the body limit of the request,
+/// every field can be given its own size limit.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Request, Response};
+///
+/// async fn avatar(req: Request) -> Result<Response, Error> {
+///     let mut multipart = req
+///         .multipart()?
+///         .field_limit(1024)
+///         .field_limit_for("avatar", 2 * 1024 * 1024);
+///
+///     let mut caption = String::new();
+///
+///     while let Some(field) = multipart.next_field().await? {
+///         match field.name() {
+///             Some("caption") => caption = field.text().await?,
+///             Some("avatar") => field.spool().await?.persist("avatars/1.png").await?,
+///             _ => {}
+///         }
+///     }
+///
+///     Ok(Response::empty())
+/// }
+/// ```
+pub struct Multipart {
+    inner: multer::Multipart<'static>,
+    field_limit: Option<usize>,
+    field_limits: HashMap<String, usize>,
+    spool_threshold: usize,
+    temp_dir: Option<PathBuf>,
+}
+
+/// Carries the errors of the request body, like an exceeded body limit, through `multer`.
+#[derive(Debug)]
+struct BodyError(Error);
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+impl Multipart {
+    pub(crate) fn new(chunks: Chunks, boundary: String) -> Self {
+        let stream = futures_util::stream::unfold(chunks, |mut chunks| async move {
+            let chunk = chunks.next().await?.map_err(BodyError);
+            Some((chunk, chunks))
+        });
+
+        Self {
+            inner: multer::Multipart::new(stream, boundary),
+            field_limit: None,
+            field_limits: HashMap::new(),
+            spool_threshold: DEFAULT_SPOOL_THRESHOLD,
+            temp_dir: None,
+        }
+    }
+
+    /// Sets the maximum size of every field, in bytes.
+    ///
+    /// Without it, fields are only bounded by the body limit of the request.
+    pub fn field_limit(mut self, limit: usize) -> Self {
+        self.field_limit = Some(limit);
+        self
+    }
+
+    /// Sets the maximum size of the field `name`, overriding [`Multipart::field_limit`].
+    pub fn field_limit_for<N: Into<String>>(mut self, name: N, limit: usize) -> Self {
+        self.field_limits.insert(name.into(), limit);
+        self
+    }
+
+    /// Sets the size above which [`Field::spool`] writes to a temporary file
+    /// instead of memory, [`DEFAULT_SPOOL_THRESHOLD`] by default.
+    pub fn spool_threshold(mut self, threshold: usize) -> Self {
+        self.spool_threshold = threshold;
+        self
+    }
+
+    /// Sets where temporary files are created, the system temporary directory by default.
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// Waits for the next field, skipping what's left of the previous one.
+    ///
+    /// Returns `None` once every field has been read. Fails with `400 Bad Request`
+    /// if the body is malformed, or `413 Payload Too Large` if it exceeds the body limit.
+    pub async fn next_field(&mut self) -> Result<Option<Field>, Error> {
+        let Some(inner) = self.inner.next_field().await.map_err(rejection)? else {
+            return Ok(None);
+        };
+
+        let limit = inner
+            .name()
+            .and_then(|name| self.field_limits.get(name).copied())
+            .or(self.field_limit);
+
+        Ok(Some(Field {
+            inner,
+            limit,
+            read: 0,
+            spool_threshold: self.spool_threshold,
+            temp_dir: self.temp_dir.clone(),
+        }))
+    }
+}
+
+/// A single field of a [`Multipart`] body.
+pub struct Field {
+    inner: multer::Field<'static>,
+    limit: Option<usize>,
+    read: usize,
+    spool_threshold: usize,
+    temp_dir: Option<PathBuf>,
+}
+
+impl Field {
+    /// The name of the field in the form.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// The name of the uploaded file, as sent by the client.
+    ///
+    /// Never use it as a path as is, it can contain anything.
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    /// The `Content-Type` of the field, as sent by the client.
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type().map(|mime| mime.essence_str())
+    }
+
+    /// Waits for the next chunk of data.
+    ///
+    /// Returns `None` once the field has been fully read.
+    /// Fails with `413 Payload Too Large` once the field exceeds its limit.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        let Some(chunk) = self.inner.chunk().await.map_err(rejection)? else {
+            return Ok(None);
+        };
+
+        self.read += chunk.len();
+
+        if let Some(limit) = self.limit
+            && self.read > limit
+        {
+            return Err(Error::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "field_too_large",
+                format!(
+                    "Field {} exceeds the limit of {} bytes",
+                    self.name().unwrap_or_default(),
+                    limit
+                ),
+            ));
+        }
+
+        Ok(Some(chunk))
+    }
+
+    /// Reads the whole field in memory.
+    pub async fn bytes(mut self) -> Result<Bytes, Error> {
+        let mut buf = BytesMut::new();
+
+        while let Some(chunk) = self.chunk().await? {
+            buf.extend_from_slice(&chunk);
+        }
+
+        Ok(buf.freeze())
+    }
+
+    /// Reads the whole field as UTF-8 text.
+    ///
+    /// Fails with `400 Bad Request` if the field isn't valid UTF-8.
+    pub async fn text(self) -> Result<String, Error> {
+        let bytes = self.bytes().await?;
+
+        String::from_utf8(bytes.into()).map_err(|_| {
+            Error::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_utf8",
+                "Multipart field is not valid UTF-8",
+            )
+        })
+    }
+
+    /// Reads the whole field, keeping it in memory while it's small
+    /// and moving it to a temporary file once it grows past the spool threshold.
+    pub async fn spool(mut self) -> Result<Spooled, Error> {
+        let mut buf = BytesMut::new();
+
+        while buf.len() <= self.spool_threshold {
+            match self.chunk().await? {
+                Some(chunk) => buf.extend_from_slice(&chunk),
+                None => return Ok(Spooled::Memory(buf.freeze())),
+            }
+        }
+
+        let dir = self.temp_dir.take().unwrap_or_else(std::env::temp_dir);
+        let temp = spawn_blocking(move || tempfile::NamedTempFile::new_in(dir))
+            .await
+            .map_err(Error::from)??;
+
+        let (file, path) = temp.into_parts();
+        let mut file = File::from_std(file);
+        let mut len = buf.len() as u64;
+
+        file.write_all(&buf).await?;
+
+        while let Some(chunk) = self.chunk().await? {
+            file.write_all(&chunk).await?;
+            len += chunk.len() as u64;
+        }
+
+        file.flush().await?;
+
+        Ok(Spooled::File { path, len })
+    }
+}
+
+/// A field read with [`Field::spool`].
+#[derive(Debug)]
+pub enum Spooled {
+    Memory(Bytes),
+
+    /// A temporary file, deleted when dropped unless [persisted](Spooled::persist)
+    File {
+        path: TempPath,
+        len: u64,
+    },
+}
+
+impl Spooled {
+    /// Size of the field, in bytes.
+    pub fn len(&self) -> u64 {
+        match self {
+            Spooled::Memory(bytes) => bytes.len() as u64,
+            Spooled::File { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads the whole field in memory.
+    pub async fn bytes(&self) -> io::Result<Bytes> {
+        match self {
+            Spooled::Memory(bytes) => Ok(bytes.clone()),
+            Spooled::File { path, .. } => tokio::fs::read(path).await.map(Bytes::from),
+        }
+    }
+
+    /// Stores the field at `dest`, replacing any existing file.
+    ///
+    /// Files moved from the temporary directory keep its owner-only permissions.
+    pub async fn persist<P: AsRef<Path>>(self, dest: P) -> io::Result<()> {
+        let dest = dest.as_ref();
+
+        match self {
+            Spooled::Memory(bytes) => tokio::fs::write(dest, bytes).await,
+            Spooled::File { path, .. } => {
+                // Renaming fails across filesystems, where the file has to be copied
+                if tokio::fs::rename(&path, dest).await.is_ok() {
+                    let _ = path.keep();
+                    return Ok(());
+                }
+
+                tokio::fs::copy(&path, dest).await.map(|_| ())
+            }
+        }
+    }
+}
+
+fn rejection(e: multer::Error) -> Error {
+    match e {
+        multer::Error::StreamReadFailed(e) => match e.downcast::<BodyError>() {
+            Ok(e) => e.0,
+            Err(e) => Error::new(
+                StatusCode::BAD_REQUEST,
+                "body_read",
+                "Failed to read the request body",
+            )
+            .with_source(e),
+        },
+
+        e => Error::new(StatusCode::BAD_REQUEST, "invalid_multipart", e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Request;
+    use http_body_util::Full;
+    use hyper::header::CONTENT_TYPE;
+
+    const BOUNDARY: &str = "casino";
+
+    /// A multipart body holding `(name, file name, content)` fields, with the given body limit.
+    fn multipart(fields: &[(&str, Option<&str>, &[u8])], body_limit: usize) -> Multipart {
+        let mut body = Vec::new();
+
+        for (name, file_name, content) in fields {
+            let disposition = match file_name {
+                Some(file_name) => format!(
+                    "form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: image/png",
+                    name, file_name
+                ),
+                None => format!("form-data; name=\"{}\"", name),
+            };
+
+            body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+            body.extend_from_slice(
+                format!("Content-Disposition: {}\r\n\r\n", disposition).as_bytes(),
+            );
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+
+        let req = hyper::Request::builder()
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Full::new(Bytes::from(body)))
+            .unwrap();
+
+        let mut req = Request::from(req);
+        req.set_body_limit(body_limit);
+        req.multipart().unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_fields_in_order() {
+        let mut multipart = multipart(
+            &[
+                ("caption", None, b"all in"),
+                ("avatar", Some("me.png"), b"\x89PNG"),
+            ],
+            1024,
+        );
+
+        let caption = multipart.next_field().await.unwrap().unwrap();
+        assert_eq!(caption.name(), Some("caption"));
+        assert_eq!(caption.text().await.unwrap(), "all in");
+
+        let avatar = multipart.next_field().await.unwrap().unwrap();
+        assert_eq!(avatar.file_name(), Some("me.png"));
+        assert_eq!(avatar.content_type(), Some("image/png"));
+        assert_eq!(avatar.bytes().await.unwrap(), &b"\x89PNG"[..]);
+
+        assert!(multipart.next_field().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_fields_over_their_limit() {
+        let avatar = [0; 64];
+        let fields = [
+            ("caption", None, b"all in".as_slice()),
+            ("avatar", Some("me.png"), &avatar),
+        ];
+
+        let mut limited = multipart(&fields, 1024).field_limit(6);
+        let caption = limited.next_field().await.unwrap().unwrap();
+        assert_eq!(caption.text().await.unwrap(), "all in");
+
+        let avatar = limited.next_field().await.unwrap().unwrap();
+        let err = avatar.bytes().await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(err.code(), "field_too_large");
+
+        // Limits of a single field take precedence
+        let mut limited = multipart(&fields, 1024)
+            .field_limit(6)
+            .field_limit_for("avatar", 64);
+        limited.next_field().await.unwrap().unwrap();
+
+        let avatar = limited.next_field().await.unwrap().unwrap();
+        assert_eq!(avatar.bytes().await.unwrap().len(), 64);
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_over_the_body_limit() {
+        let avatar = [0; 2048];
+        let mut multipart = multipart(&[("avatar", Some("me.png"), &avatar)], 1024);
+
+        let err = match multipart.next_field().await {
+            Ok(field) => field.unwrap().bytes().await.unwrap_err(),
+            Err(e) => e,
+        };
+
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn keeps_small_parts_in_memory() {
+        let mut multipart = multipart(&[("avatar", Some("me.png"), b"\x89PNG")], 1024);
+
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let spooled = field.spool().await.unwrap();
+
+        assert!(matches!(spooled, Spooled::Memory(_)));
+        assert_eq!(spooled.bytes().await.unwrap(), &b"\x89PNG"[..]);
+    }
+
+    #[tokio::test]
+    async fn spools_large_parts_to_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let avatar = (0..=255).cycle().take(64 * 1024).collect::<Vec<u8>>();
+
+        let mut multipart = multipart(&[("avatar", Some("me.png"), &avatar)], 1024 * 1024)
+            .spool_threshold(1024)
+            .temp_dir(dir.path());
+
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let spooled = field.spool().await.unwrap();
+
+        let Spooled::File { ref path, len } = spooled else {
+            panic!("expected the field to be spooled to a file");
+        };
+
+        let temp = path.to_path_buf();
+        assert!(temp.starts_with(dir.path()));
+        assert_eq!(len, avatar.len() as u64);
+        assert_eq!(spooled.bytes().await.unwrap(), avatar);
+
+        let dest = dir.path().join("avatar.png");
+        spooled.persist(&dest).await.unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), avatar);
+        assert!(!temp.exists());
+    }
+
+    #[tokio::test]
+    async fn deletes_dropped_spooled_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let avatar = [0; 4096];
+
+        let mut multipart = multipart(&[("avatar", Some("me.png"), &avatar)], 1024 * 1024)
+            .spool_threshold(1024)
+            .temp_dir(dir.path());
+
+        let spooled = multipart
+            .next_field()
+            .await
+            .unwrap()
+            .unwrap()
+            .spool()
+            .await
+            .unwrap();
+        let Spooled::File { ref path, .. } = spooled else {
+            panic!("expected the field to be spooled to a file");
+        };
+
+        let temp = path.to_path_buf();
+        assert!(temp.exists());
+
+        drop(spooled);
+        assert!(!temp.exists());
+    }
+}
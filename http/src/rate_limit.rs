@@ -0,0 +1,406 @@
+use crate::{
+    error::Error,
+    middleware::Next,
+    request::Request,
+    response::Response,
+    traits::{ApiHandler, Middleware},
+};
+use async_trait::async_trait;
+use hyper::{
+    StatusCode,
+    header::{HeaderName, HeaderValue, RETRY_AFTER},
+};
+use mini_moka::sync::Cache;
+use std::{
+    hash::{BuildHasher, RandomState},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use traccia::error;
+
+/// Most keys tracked at once by a [`RateLimit`] by default.
+pub const DEFAULT_RATE_LIMIT_KEYS: u64 = 10_000;
+
+/// How many locks new buckets are created under, keys being spread across them.
+const STRIPES: usize = 16;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+type KeyFn = dyn Fn(&Request) -> Option<String> + Send + Sync;
+
+/// Token bucket rate limiter, keyed by client.
+///
+/// Every key gets a bucket holding up to `requests` tokens, refilled
+/// continuously so that it's full again after `period`. Each request takes
+/// a token; when the bucket is empty the request is rejected with
+/// `429 Too Many Requests` and a `Retry-After` header telling when
+/// the next token will be available.
+///
+/// Responses carry `RateLimit-Limit`, `RateLimit-Remaining`, `RateLimit-Reset`
+/// and `RateLimit-Policy` headers so well-behaved clients can slow down.
+///
+/// Requests are keyed by the IP of the peer by default, see [`RateLimit::key_by`].
+/// Buckets live in memory, at most [`DEFAULT_RATE_LIMIT_KEYS`] of them,
+/// and idle ones are dropped once they'd be full again.
+///
+/// The limiter can be used as a [`Middleware`] for the whole app,
+/// or [wrap](RateLimit::wrap) single routes with their own budget.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{App, Error, RateLimit, Request, Response, Router};
+/// use std::time::Duration;
+///
+/// async fn login(req: Request) -> Result<Response, Error> {
+///     Ok(Response::empty())
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> tokio::io::Result<()> {
+///     // At most 5 logins per minute from the same IP
+///     let router = Router::new().post(
+///         "/session",
+///         RateLimit::new(5, Duration::from_secs(60)).wrap(login),
+///     );
+///
+///     App::new("127.0.0.1:5050".parse().unwrap())
+///         .await?
+///         // And 100 requests every 10 seconds overall
+///         .layer(RateLimit::new(100, Duration::from_secs(10)))
+///         .run(router)
+///         .await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RateLimit {
+    requests: u32,
+    period: Duration,
+    key: Arc<KeyFn>,
+    buckets: Cache<String, Arc<Mutex<Bucket>>>,
+
+    /// Held while looking up a missing bucket and inserting it, so concurrent
+    /// first requests of a key share one bucket instead of each getting a full one
+    stripes: [Mutex<()>; STRIPES],
+    hasher: RandomState,
+}
+
+/// The outcome of taking a token.
+struct Decision {
+    allowed: bool,
+    remaining: u32,
+
+    /// Until the bucket is full again
+    reset: Duration,
+
+    /// Until the next token is available, when none is left
+    retry_after: Duration,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimit {
+    /// Allows bursts of `requests` requests per key, refilled over `period`.
+    ///
+    /// # Panics
+    ///
+    /// If `requests` or `period` is zero.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        assert!(requests > 0, "a rate limit must allow at least one request");
+        assert!(!period.is_zero(), "a rate limit period can't be zero");
+
+        Self {
+            requests,
+            period,
+            key: Arc::new(|req: &Request| req.peer_addr().map(|peer| peer.ip().to_string())),
+            buckets: Self::cache(DEFAULT_RATE_LIMIT_KEYS, period),
+            stripes: Default::default(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Sets how requests are grouped, e.g. by session or API key instead of IP.
+    ///
+    /// Requests for which `key` returns `None` are not limited.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use http::RateLimit;
+    /// use std::time::Duration;
+    ///
+    /// let limit = RateLimit::new(60, Duration::from_secs(60)).key_by(|req| {
+    ///     req.headers()
+    ///         .get("x-api-key")
+    ///         .and_then(|v| v.to_str().ok())
+    ///         .map(str::to_string)
+    /// });
+    /// ```
+    pub fn key_by<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// Sets how many keys are tracked at once, [`DEFAULT_RATE_LIMIT_KEYS`] by default.
+    ///
+    /// When full, the least used buckets are evicted, giving those clients a fresh budget.
+    pub fn max_keys(mut self, max: u64) -> Self {
+        self.buckets = Self::cache(max, self.period);
+        self
+    }
+
+    /// Limits a single handler, typically one route, with this budget.
+    pub fn wrap<H: ApiHandler>(self, handler: H) -> RateLimited<H> {
+        RateLimited {
+            limit: self,
+            handler,
+        }
+    }
+
+    fn cache(max: u64, period: Duration) -> Cache<String, Arc<Mutex<Bucket>>> {
+        // A bucket left alone for a whole period is full, forgetting it changes nothing
+        Cache::builder()
+            .max_capacity(max)
+            .time_to_idle(period)
+            .build()
+    }
+
+    /// The bucket of `key`, created full if there's none yet.
+    fn bucket(&self, key: String) -> Arc<Mutex<Bucket>> {
+        if let Some(bucket) = self.buckets.get(&key) {
+            return bucket;
+        }
+
+        let stripe = self.hasher.hash_one(&key) as usize % STRIPES;
+        let _creating = self.stripes[stripe].lock().unwrap();
+
+        // Another request may have created it while waiting for the lock
+        if let Some(bucket) = self.buckets.get(&key) {
+            return bucket;
+        }
+
+        let bucket = Arc::new(Mutex::new(Bucket {
+            tokens: self.requests as f64,
+            updated: Instant::now(),
+        }));
+
+        self.buckets.insert(key, bucket.clone());
+        bucket
+    }
+
+    fn take(&self, key: String) -> Decision {
+        let bucket = self.bucket(key);
+
+        let capacity = self.requests as f64;
+        let per_token = self.period.as_secs_f64() / capacity;
+
+        let mut bucket = bucket.lock().unwrap();
+        let now = Instant::now();
+
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() / per_token;
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        Decision {
+            allowed,
+            remaining: bucket.tokens.floor() as u32,
+            reset: Duration::from_secs_f64((capacity - bucket.tokens) * per_token),
+            retry_after: Duration::from_secs_f64((1.0 - bucket.tokens).max(0.0) * per_token),
+        }
+    }
+
+    /// Takes a token for the key of `req`, `None` if the request isn't limited.
+    fn check(&self, req: &Request) -> Option<Decision> {
+        (self.key)(req).map(|key| self.take(key))
+    }
+
+    fn reject(&self, decision: &Decision) -> Response {
+        Response::from(Error::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            "Too many requests, slow down",
+        ))
+        .header(
+            RETRY_AFTER,
+            HeaderValue::from(ceil_secs(decision.retry_after)),
+        )
+    }
+
+    /// Adds the `RateLimit-*` headers, unless a stricter limit
+    /// (e.g. the one of a route under an app-wide one) already did.
+    fn decorate(&self, decision: &Decision, res: &mut Response) {
+        let headers = res.headers_mut();
+
+        let stricter = headers
+            .get(RATELIMIT_REMAINING)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u32>().ok())
+            .is_some_and(|remaining| remaining <= decision.remaining);
+
+        if stricter {
+            return;
+        }
+
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.requests));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+        headers.insert(
+            RATELIMIT_RESET,
+            HeaderValue::from(ceil_secs(decision.reset)),
+        );
+
+        let policy = format!("{};w={}", self.requests, ceil_secs(self.period));
+        if let Ok(policy) = HeaderValue::from_str(&policy) {
+            headers.insert(RATELIMIT_POLICY, policy);
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    async fn handle(&self, req: Request, next: Next<'_>) -> Result<Response, Error> {
+        let Some(decision) = self.check(&req) else {
+            return next.run(req).await;
+        };
+
+        let (id, peer) = (req.request_id().to_string(), req.peer_addr());
+
+        let mut res = match decision.allowed {
+            true => next.run(req).await.unwrap_or_else(|e| render(&id, peer, e)),
+            false => self.reject(&decision),
+        };
+
+        self.decorate(&decision, &mut res);
+        Ok(res)
+    }
+}
+
+/// A handler with its own [`RateLimit`], see [`RateLimit::wrap`].
+pub struct RateLimited<H> {
+    limit: RateLimit,
+    handler: H,
+}
+
+#[async_trait]
+impl<H: ApiHandler> ApiHandler for RateLimited<H> {
+    async fn incoming(&self, req: Request) -> Result<Response, Error> {
+        let Some(decision) = self.limit.check(&req) else {
+            return self.handler.incoming(req).await;
+        };
+
+        let (id, peer) = (req.request_id().to_string(), req.peer_addr());
+
+        let mut res = match decision.allowed {
+            true => self
+                .handler
+                .incoming(req)
+                .await
+                .unwrap_or_else(|e| render(&id, peer, e)),
+            false => self.limit.reject(&decision),
+        };
+
+        self.limit.decorate(&decision, &mut res);
+        Ok(res)
+    }
+}
+
+/// Turns the error of a limited handler into a response, so that it carries
+/// the `RateLimit-*` headers like any other request that took a token.
+///
+/// Server errors are logged here, as the app only gets the response.
+fn render(id: &str, peer: Option<SocketAddr>, err: Error) -> Response {
+    if err.status().is_server_error() {
+        let peer = peer.map_or_else(|| "-".to_string(), |peer| peer.to_string());
+        error!("request_id={} peer={} {}", id, peer, err);
+    }
+
+    err.into()
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{middleware::Stack, testing::TestClient};
+    use std::sync::Barrier;
+
+    async fn fail(_: Request) -> Result<Response, Error> {
+        Err(Error::bad_request("No chips left"))
+    }
+
+    fn limit() -> RateLimit {
+        RateLimit::new(2, Duration::from_secs(60)).key_by(|_| Some("table".to_string()))
+    }
+
+    #[tokio::test]
+    async fn decorates_errors_of_wrapped_handlers() {
+        let client = TestClient::new(limit().wrap(fail));
+
+        let res = client.get("/").send().await;
+        res.assert_status(StatusCode::BAD_REQUEST)
+            .assert_header("ratelimit-remaining", "1");
+
+        client.get("/").send().await;
+        let res = client.get("/").send().await;
+        res.assert_status(StatusCode::TOO_MANY_REQUESTS)
+            .assert_header("ratelimit-remaining", "0");
+    }
+
+    #[tokio::test]
+    async fn decorates_errors_under_the_middleware() {
+        let layers: Vec<Box<dyn Middleware>> = vec![Box::new(limit())];
+        let client = TestClient::new(Stack::new(layers, fail));
+
+        let res = client.get("/").send().await;
+        res.assert_status(StatusCode::BAD_REQUEST)
+            .assert_header("ratelimit-limit", "2")
+            .assert_header("ratelimit-remaining", "1");
+    }
+
+    #[test]
+    fn shares_new_buckets_between_concurrent_requests() {
+        let limit = RateLimit::new(3, Duration::from_secs(60));
+        let threads = 8;
+
+        for key in 0..500 {
+            let barrier = Barrier::new(threads);
+
+            let allowed = std::thread::scope(|scope| {
+                let handles = (0..threads)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            barrier.wait();
+                            limit.take(key.to_string()).allowed
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .filter(|allowed| *allowed)
+                    .count()
+            });
+
+            assert_eq!(allowed, 3, "key {}", key);
+        }
+    }
+}
@@ -1,15 +1,58 @@
+use crate::{
+    body::{Chunks, DEFAULT_BODY_LIMIT, RequestBody, too_large},
+    conditional::{Conditions, ETag, precondition_failed},
+    cookie::Cookies,
+    error::{BoxError, Error},
+    format::{Format, not_acceptable, request_format},
+    multipart::Multipart,
+    state::StateMap,
+    websocket::{Session, WebSocketUpgrade},
+};
+use bytes::{Bytes, BytesMut};
 use http_body_util::BodyExt;
-use hyper::body::Incoming;
-use serde::Deserialize;
-use std::ops::{Deref, DerefMut};
+use hyper::{
+    StatusCode,
+    body::Body,
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+};
+use nanoid::nanoid;
+use serde::{Deserialize, de::DeserializeOwned};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::sync::mpsc::UnboundedSender;
 
 pub struct Request {
-    inner: hyper::Request<Incoming>,
+    inner: hyper::Request<RequestBody>,
     segments: Vec<String>,
+    params: HashMap<String, String>,
+    body_limit: usize,
+    sessions: Option<UnboundedSender<Session>>,
+    id: String,
+    peer: Option<SocketAddr>,
+    state: Option<Arc<StateMap>>,
 }
 
-impl From<hyper::Request<Incoming>> for Request {
-    fn from(inner: hyper::Request<Incoming>) -> Self {
+/// Header carrying the id of a request, set by proxies or clients and echoed back.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest incoming request id that is honored, longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Wraps a request with any body, such as the `Incoming` one of a connection
+/// or a `Full` one built in memory (see [`testing`](crate::testing)).
+impl<B> From<hyper::Request<B>> for Request
+where
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+{
+    fn from(inner: hyper::Request<B>) -> Self {
+        let inner = inner.map(|body| body.map_err(Into::into).boxed());
         let path = inner.uri().path();
         let segments = path
             .split('/')
@@ -17,12 +60,30 @@ impl From<hyper::Request<Incoming>> for Request {
             .map(|s| s.to_string())
             .collect::<Vec<_>>();
 
-        Self { inner, segments }
+        // Ids coming from outside end up in logs, so only plain printable ones are kept
+        let id = inner
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+            .filter(|id| id.bytes().all(|b| b.is_ascii_graphic()))
+            .map_or_else(|| nanoid!(), str::to_string);
+
+        Self {
+            inner,
+            segments,
+            params: HashMap::new(),
+            body_limit: DEFAULT_BODY_LIMIT,
+            sessions: None,
+            id,
+            peer: None,
+            state: None,
+        }
     }
 }
 
 impl Deref for Request {
-    type Target = hyper::Request<Incoming>;
+    type Target = hyper::Request<RequestBody>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
@@ -40,10 +101,364 @@ impl Request {
         self.segments.iter().map(|s| s.as_str()).collect::<Vec<_>>()
     }
 
-    pub async fn json<B: for<'a> Deserialize<'a>>(self) -> serde_json::Result<B> {
-        let body = self.inner.into_body();
-        let bytes = body.collect().await.unwrap().to_bytes();
+    /// The id of the last Server-Sent Event received by a reconnecting client,
+    /// from the `Last-Event-ID` header.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.headers()
+            .get("last-event-id")
+            .and_then(|v| v.to_str().ok())
+    }
+
+    /// Path parameters captured by the [`Router`](crate::Router),
+    /// keyed by the name used in the route pattern.
+    pub fn params(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+
+    /// Returns the path parameter `name` parsed as `T`.
+    ///
+    /// Returns `None` if the parameter is missing or can't be parsed.
+    pub fn param<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.params.get(name).and_then(|v| v.parse().ok())
+    }
+
+    /// Deserializes the query string into `T`.
+    ///
+    /// A missing query string is treated as an empty one, so `T` can still
+    /// be built if all of its fields are optional.
+    /// Fails with `400 Bad Request` if the query doesn't match `T`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use http::{Error, Request, Response};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Page {
+    ///     page: Option<u32>,
+    ///     limit: Option<u32>,
+    /// }
+    ///
+    /// async fn list(req: Request) -> Result<Response, Error> {
+    ///     // GET /rooms?page=2&limit=50
+    ///     let page = req.query::<Page>()?;
+    ///     Ok(Response::empty())
+    /// }
+    /// ```
+    pub fn query<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let query = self.uri().query().unwrap_or_default();
+
+        serde_urlencoded::from_str(query)
+            .map_err(|e| Error::new(StatusCode::BAD_REQUEST, "invalid_query", e.to_string()))
+    }
+
+    /// Returns every query parameter, decoded, keyed by name.
+    ///
+    /// Repeated keys keep all their values in order of appearance,
+    /// so `?tag=a&tag=b` yields `{"tag": ["a", "b"]}`.
+    pub fn query_map(&self) -> HashMap<String, Vec<String>> {
+        let query = self.uri().query().unwrap_or_default();
+        let mut map = HashMap::<String, Vec<String>>::new();
+
+        for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+            map.entry(k.into_owned()).or_default().push(v.into_owned());
+        }
+
+        map
+    }
+
+    /// Identifies the request in logs, taken from the `X-Request-Id` header
+    /// when the client or a proxy sent one, or generated otherwise.
+    ///
+    /// The [`App`](crate::App) sends it back in the `X-Request-Id` response header.
+    pub fn request_id(&self) -> &str {
+        &self.id
+    }
+
+    /// Address of the client at the other end of the connection.
+    ///
+    /// Behind a reverse proxy, this is the address of the proxy.
+    /// Only `None` for requests that didn't come from a connection, e.g. in tests.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    pub(crate) fn set_peer_addr(&mut self, peer: SocketAddr) {
+        self.peer = Some(peer);
+    }
+
+    /// The cookies sent with the request, parsed from its `Cookie` headers.
+    pub fn cookies(&self) -> Cookies {
+        Cookies::from_headers(self.headers())
+    }
+
+    pub(crate) fn set_params(&mut self, params: HashMap<String, String>) {
+        self.params = params;
+    }
+
+    /// Maximum number of body bytes that will be read from this request.
+    pub fn body_limit(&self) -> usize {
+        self.body_limit
+    }
+
+    pub(crate) fn set_body_limit(&mut self, limit: usize) {
+        self.body_limit = limit;
+    }
+
+    pub(crate) fn set_sessions(&mut self, sessions: UnboundedSender<Session>) {
+        self.sessions = Some(sessions);
+    }
+
+    /// Moves the request out, leaving an empty one behind.
+    pub(crate) fn take(&mut self) -> Request {
+        let empty = Request {
+            inner: hyper::Request::new(RequestBody::default()),
+            segments: Vec::new(),
+            params: HashMap::new(),
+            body_limit: self.body_limit,
+            sessions: None,
+            id: String::new(),
+            peer: None,
+            state: None,
+        };
+
+        std::mem::replace(self, empty)
+    }
+
+    /// The value of type `T` registered with [`App::state`](crate::App::state).
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.as_ref().and_then(|state| state.get())
+    }
+
+    pub(crate) fn set_state(&mut self, state: Arc<StateMap>) {
+        self.state = Some(state);
+    }
+
+    /// Accepts a WebSocket upgrade request.
+    ///
+    /// Fails with `400 Bad Request` if this isn't a valid upgrade request,
+    /// or `426 Upgrade Required` if the client speaks an unsupported WebSocket version.
+    pub fn websocket(&mut self) -> Result<WebSocketUpgrade, Error> {
+        let headers = self.inner.headers().clone();
+        let on_upgrade = hyper::upgrade::on(&mut self.inner);
+
+        WebSocketUpgrade::new(&headers, on_upgrade, self.sessions.clone())
+    }
+
+    /// Returns a stream over the body chunks, for reading large bodies
+    /// without buffering them in memory.
+    ///
+    /// Fails with `413 Payload Too Large` if the declared `Content-Length`
+    /// already exceeds the body limit.
+    pub fn chunks(mut self) -> Result<Chunks, Error> {
+        self.take_chunks()
+    }
+
+    /// Like [`Request::chunks`], leaving an empty body behind.
+    pub(crate) fn take_chunks(&mut self) -> Result<Chunks, Error> {
+        let declared = self
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<usize>().ok());
+
+        if declared.is_some_and(|len| len > self.body_limit) {
+            return Err(too_large(self.body_limit));
+        }
+
+        let body = std::mem::take(self.inner.body_mut());
+        Ok(Chunks::new(body, self.body_limit))
+    }
+
+    /// Reads the whole body.
+    ///
+    /// Fails with `413 Payload Too Large` if the body exceeds the limit,
+    /// or `400 Bad Request` if it can't be read.
+    pub async fn bytes(mut self) -> Result<Bytes, Error> {
+        self.take_bytes().await
+    }
+
+    pub(crate) async fn take_bytes(&mut self) -> Result<Bytes, Error> {
+        let mut chunks = self.take_chunks()?;
+        let mut buf = BytesMut::new();
+
+        while let Some(chunk) = chunks.next().await {
+            buf.extend_from_slice(&chunk?);
+        }
+
+        Ok(buf.freeze())
+    }
+
+    /// Reads the whole body as UTF-8 text.
+    ///
+    /// Fails like [`Request::bytes`], or with `400 Bad Request` if the body isn't valid UTF-8.
+    pub async fn text(self) -> Result<String, Error> {
+        let bytes = self.bytes().await?;
+
+        String::from_utf8(bytes.into()).map_err(|_| {
+            Error::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_utf8",
+                "Request body is not valid UTF-8",
+            )
+        })
+    }
+
+    /// Reads the whole body and deserializes it from JSON.
+    ///
+    /// Fails like [`Request::bytes`], or with `400 Bad Request` if the body isn't valid JSON for `B`.
+    pub async fn json<B: for<'a> Deserialize<'a>>(mut self) -> Result<B, Error> {
+        self.take_json().await
+    }
+
+    pub(crate) async fn take_json<B: for<'a> Deserialize<'a>>(&mut self) -> Result<B, Error> {
+        let bytes = self.take_bytes().await?;
 
         serde_json::from_slice(&bytes)
+            .map_err(|e| Error::new(StatusCode::BAD_REQUEST, "invalid_json", e.to_string()))
+    }
+
+    /// Deserializes the body in the format given by its `Content-Type`:
+    /// JSON, MessagePack, CBOR or a urlencoded form.
+    ///
+    /// A body without `Content-Type` is read as JSON. Fails like [`Request::bytes`],
+    /// with `415 Unsupported Media Type` for other content types,
+    /// or `400 Bad Request` if the body doesn't match `T`.
+    pub async fn payload<T: DeserializeOwned>(mut self) -> Result<T, Error> {
+        self.take_payload().await
+    }
+
+    pub(crate) async fn take_payload<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        let urlencoded = self
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| {
+                v.split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+            });
+
+        if urlencoded {
+            return self.take_form().await;
+        }
+
+        let format = request_format(self.headers())?;
+        let bytes = self.take_bytes().await?;
+
+        format.deserialize(&bytes)
+    }
+
+    /// The response format the client prefers according to its `Accept` header,
+    /// JSON if it accepts none of the supported ones.
+    ///
+    /// See [`Request::accepted_format`] to reject such clients instead.
+    pub fn preferred_format(&self) -> Format {
+        Format::negotiate(self.headers()).unwrap_or_default()
+    }
+
+    /// Like [`Request::preferred_format`], failing with `406 Not Acceptable`
+    /// if the client accepts none of the supported formats.
+    pub fn accepted_format(&self) -> Result<Format, Error> {
+        Format::negotiate(self.headers()).ok_or_else(not_acceptable)
+    }
+
+    /// Checks the `If-Match`, `If-Unmodified-Since` and `If-None-Match` headers against
+    /// the current version of the resource, before changing it.
+    ///
+    /// Pass `None` for both when the resource doesn't exist yet, so that
+    /// `If-None-Match: *` only lets the first of concurrent clients create it.
+    ///
+    /// The [`App`](crate::App) checks them on its own for `GET` and `HEAD`, once the
+    /// handler has responded, which is too late for methods that change state.
+    /// Fails with `412 Precondition Failed`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use http::{ETag, Error, Request, Response};
+    ///
+    /// async fn rename_table(req: Request) -> Result<Response, Error> {
+    ///     let current = ETag::strong("table-42-v7");
+    ///     req.preconditions(Some(&current), None)?;
+    ///
+    ///     // The client renamed the version it knew, apply the change
+    ///     Ok(Response::empty().etag(ETag::strong("table-42-v8")))
+    /// }
+    /// ```
+    pub fn preconditions(
+        &self,
+        etag: Option<&ETag>,
+        modified: Option<SystemTime>,
+    ) -> Result<(), Error> {
+        let exists = etag.is_some() || modified.is_some();
+
+        match Conditions::new(self.headers()).evaluate(self.method(), exists, etag, modified) {
+            Some(StatusCode::PRECONDITION_FAILED) => Err(precondition_failed()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deserializes an `application/x-www-form-urlencoded` body, as sent by HTML forms.
+    ///
+    /// Fails like [`Request::bytes`], or with `400 Bad Request` if the body doesn't match `T`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use http::{Error, Request, Response};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Login {
+    ///     username: String,
+    /// }
+    ///
+    /// async fn login(req: Request) -> Result<Response, Error> {
+    ///     let login = req.form::<Login>().await?;
+    ///     Ok(Response::empty())
+    /// }
+    /// ```
+    pub async fn form<T: DeserializeOwned>(mut self) -> Result<T, Error> {
+        self.take_form().await
+    }
+
+    pub(crate) async fn take_form<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        let bytes = self.take_bytes().await?;
+
+        serde_urlencoded::from_bytes(&bytes)
+            .map_err(|e| Error::new(StatusCode::BAD_REQUEST, "invalid_form", e.to_string()))
+    }
+
+    /// Reads a `multipart/form-data` body field by field, see [`Multipart`].
+    ///
+    /// Fails with `415 Unsupported Media Type` if the request isn't multipart,
+    /// or `400 Bad Request` if its `Content-Type` has no boundary.
+    pub fn multipart(mut self) -> Result<Multipart, Error> {
+        self.take_multipart()
+    }
+
+    pub(crate) fn take_multipart(&mut self) -> Result<Multipart, Error> {
+        let content_type = self
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        let boundary = multer::parse_boundary(content_type).map_err(|e| match e {
+            multer::Error::NoBoundary => {
+                Error::new(StatusCode::BAD_REQUEST, "invalid_multipart", e.to_string())
+            }
+            _ => Error::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Expected a multipart/form-data body",
+            ),
+        })?;
+
+        Ok(Multipart::new(self.take_chunks()?, boundary))
     }
 }
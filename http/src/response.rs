@@ -1,17 +1,32 @@
 use std::ops::{Deref, DerefMut};
 
+use crate::{
+    conditional::{CacheControl, ETag},
+    cookie::{Cookie, Key},
+    error::{BoxError, Error},
+    format::Format,
+};
+use ::cookie::CookieJar;
 use bytes::Bytes;
-use http_body_util::{BodyExt, Empty, Full, combinators::BoxBody};
+use futures_util::{Stream, TryStreamExt};
+use http_body_util::{BodyExt, Empty, Full, StreamBody, combinators::UnsyncBoxBody};
 use hyper::{
     Response as HyperResponse, StatusCode,
-    header::{HeaderValue, IntoHeaderName},
+    body::Frame,
+    header::{
+        CACHE_CONTROL, CONTENT_TYPE, ETAG, HeaderValue, IntoHeaderName, LAST_MODIFIED, SET_COOKIE,
+    },
 };
 use serde::Serialize;
+use std::time::SystemTime;
+
+/// The body of a [`Response`], either buffered or streamed.
+pub type Body = UnsyncBoxBody<Bytes, BoxError>;
 
-pub struct Response(HyperResponse<BoxBody<Bytes, hyper::Error>>);
+pub struct Response(HyperResponse<Body>);
 
 impl Deref for Response {
-    type Target = HyperResponse<BoxBody<Bytes, hyper::Error>>;
+    type Target = HyperResponse<Body>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -24,9 +39,9 @@ impl DerefMut for Response {
     }
 }
 
-impl Into<HyperResponse<BoxBody<Bytes, hyper::Error>>> for Response {
-    fn into(self) -> HyperResponse<BoxBody<Bytes, hyper::Error>> {
-        self.0
+impl From<Response> for HyperResponse<Body> {
+    fn from(res: Response) -> Self {
+        res.0
     }
 }
 
@@ -35,9 +50,17 @@ impl Response {
         Self(HyperResponse::new(
             Empty::<Bytes>::new()
                 .map_err(|never| match never {})
-                .boxed(),
+                .boxed_unsync(),
         ))
-        .cors()
+    }
+
+    /// Moves the body out, leaving an empty one in its place.
+    pub(crate) fn take_body(&mut self) -> Body {
+        let empty = Empty::<Bytes>::new()
+            .map_err(|never| match never {})
+            .boxed_unsync();
+
+        std::mem::replace(self.body_mut(), empty)
     }
 
     pub fn header<K: IntoHeaderName, V: Into<HeaderValue>>(mut self, k: K, v: V) -> Self {
@@ -50,34 +73,147 @@ impl Response {
         self
     }
 
-    pub fn text<T: Into<String>>(mut self, text: T) -> Self {
-        *self.body_mut() = Full::new(Bytes::from(text.into()))
+    /// Sets a plain text body, with a `text/plain; charset=utf-8` content type unless one is already set.
+    pub fn text<T: Into<String>>(self, text: T) -> Self {
+        self.bytes("text/plain; charset=utf-8", Bytes::from(text.into()))
+    }
+
+    /// Serializes `payload` as JSON and sets it as the body,
+    /// with an `application/json` content type unless one is already set.
+    ///
+    /// Fails with `500 Internal Server Error` if the payload can't be serialized.
+    pub fn body<B: Serialize>(self, payload: B) -> Result<Self, Error> {
+        self.body_as(Format::Json, payload)
+    }
+
+    /// Serializes `payload` in `format` and sets it as the body,
+    /// with the matching content type unless one is already set.
+    ///
+    /// The format is typically the one the client asked for, see [`Request::preferred_format`](crate::Request::preferred_format).
+    ///
+    /// Fails with `500 Internal Server Error` if the payload can't be serialized.
+    pub fn body_as<B: Serialize>(self, format: Format, payload: B) -> Result<Self, Error> {
+        let bytes = format.serialize(&payload)?;
+        Ok(self.bytes(format.content_type(), bytes))
+    }
+
+    fn bytes(mut self, content_type: &'static str, bytes: Bytes) -> Self {
+        self.headers_mut()
+            .entry(CONTENT_TYPE)
+            .or_insert(HeaderValue::from_static(content_type));
+
+        *self.body_mut() = Full::new(bytes)
             .map_err(|never| match never {})
-            .boxed();
+            .boxed_unsync();
 
         self
     }
 
-    pub fn body<B: Serialize>(mut self, payload: B) -> serde_json::Result<Self> {
-        let json = serde_json::to_string(&payload)?;
-        *self.body_mut() = Full::new(Bytes::from(json))
-            .map_err(|never| match never {})
-            .boxed();
+    /// Sets a body that is sent chunk by chunk, as `stream` produces them.
+    ///
+    /// If the stream yields an error, the connection is aborted.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bytes::Bytes;
+    /// use futures_util::StreamExt;
+    /// use http::Response;
+    ///
+    /// let chunks = futures_util::stream::iter(["hello", " ", "world"])
+    ///     .map(|s| Ok::<_, std::io::Error>(Bytes::from(s)));
+    ///
+    /// let res = Response::empty().stream(chunks);
+    /// ```
+    pub fn stream<S, E>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        let frames = stream.map_ok(Frame::data).map_err(Into::into);
+        *self.body_mut() = StreamBody::new(frames).boxed_unsync();
 
-        Ok(self)
+        self
     }
 
-    fn cors(self) -> Self {
-        self.header("Access-Control-Allow-Origin", HeaderValue::from_static("*"))
-        .header(
-            "Access-Control-Allow-Methods",
-            HeaderValue::from_static("GET, POST, PUT, DELETE, OPTIONS"),
-        )
-        .header(
-            "Access-Control-Allow-Headers",
-            HeaderValue::from_static(
-                "Accept, Accept-Language, Content-Language,  Origin, Content-Type, Authorization",
-            ),
+    /// Sets the `ETag` header, the validator clients revalidate their copy with.
+    ///
+    /// Requests for it with a matching `If-None-Match` are answered with `304 Not Modified`.
+    pub fn etag(self, etag: ETag) -> Self {
+        self.header(ETAG, etag)
+    }
+
+    /// Sets the `Last-Modified` header, with a one second resolution.
+    ///
+    /// Requests for it with a later `If-Modified-Since` are answered with `304 Not Modified`.
+    pub fn last_modified(self, modified: SystemTime) -> Self {
+        self.header(
+            LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(modified)).unwrap(),
         )
     }
+
+    /// Sets the `Cache-Control` header.
+    pub fn cache_control(self, cache: CacheControl) -> Self {
+        self.header(CACHE_CONTROL, cache)
+    }
+
+    /// Adds a `Set-Cookie` header for `cookie`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use http::{
+    ///     Response,
+    ///     cookie::{Cookie, Duration, SameSite},
+    /// };
+    ///
+    /// let res = Response::empty().cookie(
+    ///     Cookie::build(("theme", "dark"))
+    ///         .path("/")
+    ///         .same_site(SameSite::Lax)
+    ///         .max_age(Duration::days(365)),
+    /// );
+    /// ```
+    pub fn cookie<C: Into<Cookie<'static>>>(self, cookie: C) -> Self {
+        let mut jar = CookieJar::new();
+        jar.add(cookie);
+        self.set_cookies(jar)
+    }
+
+    /// Adds a cookie whose value is signed with `key`, so the client can
+    /// read it but any change is detected by [`Cookies::get_signed`](crate::cookie::Cookies::get_signed).
+    pub fn signed_cookie<C: Into<Cookie<'static>>>(self, cookie: C, key: &Key) -> Self {
+        let mut jar = CookieJar::new();
+        jar.signed_mut(key).add(cookie);
+        self.set_cookies(jar)
+    }
+
+    /// Adds a cookie whose value is encrypted with `key`, readable only
+    /// through [`Cookies::get_private`](crate::cookie::Cookies::get_private).
+    pub fn private_cookie<C: Into<Cookie<'static>>>(self, cookie: C, key: &Key) -> Self {
+        let mut jar = CookieJar::new();
+        jar.private_mut(key).add(cookie);
+        self.set_cookies(jar)
+    }
+
+    /// Tells the client to delete `cookie`.
+    ///
+    /// Its path and domain must match the ones it was set with.
+    pub fn remove_cookie<C: Into<Cookie<'static>>>(self, cookie: C) -> Self {
+        let mut cookie = cookie.into();
+        cookie.make_removal();
+        self.cookie(cookie)
+    }
+
+    /// Appends the cookies of `jar`, skipping the ones that can't be sent in a header.
+    fn set_cookies(mut self, jar: CookieJar) -> Self {
+        for cookie in jar.delta() {
+            if let Ok(value) = HeaderValue::from_str(&cookie.encoded().to_string()) {
+                self.headers_mut().append(SET_COOKIE, value);
+            }
+        }
+
+        self
+    }
 }
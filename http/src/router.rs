@@ -0,0 +1,336 @@
+use crate::{error::Error, request::Request, response::Response, traits::ApiHandler};
+use async_trait::async_trait;
+use hyper::{
+    Method, StatusCode,
+    header::{ALLOW, HeaderValue},
+};
+use percent_encoding::percent_decode_str;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// A single piece of a route pattern.
+enum Segment {
+    /// Must match the path segment exactly, e.g. `rooms`
+    Static(String),
+
+    /// Matches any single segment and captures it, e.g. `:id`
+    Param(String),
+
+    /// Matches the rest of the path (possibly empty) and captures it, e.g. `*path`
+    Wildcard(String),
+}
+
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    fn parse(path: &str) -> Self {
+        let segments = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                if let Some(name) = s.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = s.strip_prefix('*') {
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Static(s.to_string())
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Self { segments }
+    }
+
+    /// Matches the request segments against this pattern,
+    /// returning the captured parameters, percent-decoded, on success.
+    fn matches(&self, path: &[&str]) -> Option<HashMap<String, String>> {
+        let mut params = HashMap::new();
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Static(s) => {
+                    if path.get(i) != Some(&s.as_str()) {
+                        return None;
+                    }
+                }
+
+                Segment::Param(name) => {
+                    params.insert(name.clone(), decode(path.get(i)?));
+                }
+
+                // Wildcards swallow everything that's left, so there's nothing to check after them
+                Segment::Wildcard(name) => {
+                    params.insert(name.clone(), decode(&path[i.min(path.len())..].join("/")));
+                    return Some(params);
+                }
+            }
+        }
+
+        if path.len() == self.segments.len() {
+            Some(params)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decodes a captured segment, replacing invalid UTF-8 sequences.
+fn decode(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+struct Route {
+    method: Method,
+
+    /// The pattern as registered, e.g. `/rooms/:id`
+    path: String,
+    pattern: Pattern,
+    handler: Box<dyn ApiHandler>,
+}
+
+/// Filled by the [`Router`] with the pattern of the route a request matched,
+/// so it can be read once the request has been moved into the handler.
+///
+/// Nested routers overwrite it, so the innermost pattern is kept.
+#[derive(Clone, Default)]
+pub(crate) struct MatchedRoute(Arc<Mutex<Option<String>>>);
+
+impl MatchedRoute {
+    fn set(&self, path: &str) {
+        *self.0.lock().unwrap() = Some(path.to_string());
+    }
+
+    pub(crate) fn get(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Dispatches requests to handlers based on method and path pattern.
+///
+/// Patterns are made of `/`-separated segments, where a segment can be:
+/// - a literal, matched exactly (`/rooms`)
+/// - a parameter, prefixed by `:`, matching a single segment (`/rooms/:id`)
+/// - a wildcard, prefixed by `*`, matching the rest of the path (`/files/*path`)
+///
+/// Routes are tried in registration order and the first match wins.
+/// `HEAD` requests are handled by the `GET` route of the path unless one is
+/// registered for `HEAD`. When a path matches but the method doesn't, the router
+/// answers with `405 Method Not Allowed` and an `Allow` header listing the accepted methods.
+///
+/// Parameters are percent-decoded, so `/players/john%20doe` gives `john doe`.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Request, Response, Router};
+/// use hyper::StatusCode;
+///
+/// async fn seat(req: Request) -> Result<Response, Error> {
+///     let seat = req.param::<u8>("seat").unwrap_or_default();
+///     Ok(Response::empty().status(StatusCode::OK).text(format!("Seat {}", seat)))
+/// }
+///
+/// let router = Router::new().get("/rooms/:id/seats/:seat", seat);
+/// ```
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Option<Box<dyn ApiHandler>>,
+}
+
+impl Router {
+    /// Creates an empty router.
+    ///
+    /// Without routes, every request is answered with `404 Not Found`.
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    /// Registers a handler for the given method and path pattern.
+    pub fn route<H: ApiHandler>(mut self, method: Method, path: &str, handler: H) -> Self {
+        self.routes.push(Route {
+            method,
+            path: path.to_string(),
+            pattern: Pattern::parse(path),
+            handler: Box::new(handler),
+        });
+
+        self
+    }
+
+    pub fn get<H: ApiHandler>(self, path: &str, handler: H) -> Self {
+        self.route(Method::GET, path, handler)
+    }
+
+    pub fn post<H: ApiHandler>(self, path: &str, handler: H) -> Self {
+        self.route(Method::POST, path, handler)
+    }
+
+    pub fn put<H: ApiHandler>(self, path: &str, handler: H) -> Self {
+        self.route(Method::PUT, path, handler)
+    }
+
+    pub fn patch<H: ApiHandler>(self, path: &str, handler: H) -> Self {
+        self.route(Method::PATCH, path, handler)
+    }
+
+    pub fn delete<H: ApiHandler>(self, path: &str, handler: H) -> Self {
+        self.route(Method::DELETE, path, handler)
+    }
+
+    /// Sets the handler used when no route matches the request path.
+    ///
+    /// By default, the router answers with an empty `404 Not Found`.
+    pub fn fallback<H: ApiHandler>(mut self, handler: H) -> Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ApiHandler for Router {
+    async fn incoming(&self, mut req: Request) -> Result<Response, Error> {
+        let mut allowed = Vec::new();
+        let mut matched = None;
+
+        // The GET route answering a HEAD request, unless a HEAD route comes later
+        let mut get = None;
+
+        {
+            let segments = req.segments();
+
+            for route in &self.routes {
+                if let Some(params) = route.pattern.matches(&segments) {
+                    if route.method == req.method() {
+                        matched = Some((route, params));
+                        break;
+                    }
+
+                    if route.method == Method::GET && req.method() == Method::HEAD && get.is_none()
+                    {
+                        get = Some((route, params));
+                        continue;
+                    }
+
+                    if !allowed.contains(&route.method) {
+                        allowed.push(route.method.clone());
+                    }
+                }
+            }
+        }
+
+        let matched = matched.or(get);
+
+        if let Some((route, params)) = matched {
+            if let Some(slot) = req.extensions().get::<MatchedRoute>() {
+                slot.set(&route.path);
+            }
+
+            req.set_params(params);
+            return route.handler.incoming(req).await;
+        }
+
+        if allowed.is_empty() {
+            return match self.fallback {
+                Some(ref fallback) => fallback.incoming(req).await,
+                None => Ok(Response::empty().status(StatusCode::NOT_FOUND)),
+            };
+        }
+
+        // The path exists, so advertise what can be done with it
+        if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+            allowed.push(Method::HEAD);
+        }
+
+        allowed.push(Method::OPTIONS);
+
+        let allow = allowed
+            .iter()
+            .map(|m| m.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let status = if req.method() == Method::OPTIONS {
+            StatusCode::OK
+        } else {
+            StatusCode::METHOD_NOT_ALLOWED
+        };
+
+        Ok(Response::empty()
+            .status(status)
+            .header(ALLOW, HeaderValue::from_str(&allow).unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{extract::Path, handler, testing::TestClient};
+
+    async fn player(req: Request) -> Result<Response, Error> {
+        Ok(Response::empty().text(req.param::<String>("name").unwrap_or_default()))
+    }
+
+    async fn file(Path(path): Path<String>) -> Result<Response, Error> {
+        Ok(Response::empty().text(path))
+    }
+
+    fn client() -> TestClient<Router> {
+        TestClient::new(
+            Router::new()
+                .get("/players/:name", player)
+                .post("/players/:name", player)
+                .get("/files/*path", handler(file)),
+        )
+    }
+
+    #[tokio::test]
+    async fn decodes_params() {
+        let client = client();
+
+        let res = client.get("/players/john%20doe").send().await;
+        assert_eq!(res.text(), "john doe");
+
+        let res = client.get("/files/a%20b/c%C3%A9").send().await;
+        assert_eq!(res.text(), "a b/cé");
+    }
+
+    #[tokio::test]
+    async fn answers_head_with_the_get_route() {
+        let res = client().request(Method::HEAD, "/players/ann").send().await;
+        res.assert_status(StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn prefers_head_routes() {
+        let head = |_: Request| async { Ok(Response::empty().status(StatusCode::NO_CONTENT)) };
+        let client = TestClient::new(Router::new().get("/table", player).route(
+            Method::HEAD,
+            "/table",
+            head,
+        ));
+
+        let res = client.request(Method::HEAD, "/table").send().await;
+        res.assert_status(StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn advertises_head_for_get_routes() {
+        let res = client().delete("/players/ann").send().await;
+
+        res.assert_status(StatusCode::METHOD_NOT_ALLOWED)
+            .assert_header("allow", "GET, POST, HEAD, OPTIONS");
+    }
+}
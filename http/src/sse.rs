@@ -0,0 +1,287 @@
+use crate::{error::Error, response::Response};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures_util::Stream;
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE, HeaderValue};
+use serde::Serialize;
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+
+/// A single Server-Sent Event.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::Event;
+/// use std::time::Duration;
+///
+/// let event = Event::data("{\"balance\":100}")
+///     .event("balance")
+///     .id("42")
+///     .retry(Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl Event {
+    /// Creates an event carrying `data`.
+    ///
+    /// Multiline data is split over multiple `data:` lines at every `\r\n`, `\r`
+    /// or `\n`, the client receives it joined back with `\n`.
+    pub fn data<S: Into<String>>(data: S) -> Self {
+        Self {
+            data: Some(data.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Creates an event carrying `payload` serialized as JSON.
+    pub fn json<T: Serialize>(payload: T) -> Result<Self, Error> {
+        Ok(Self::data(serde_json::to_string(&payload)?))
+    }
+
+    /// Creates an event made of a comment only, ignored by clients.
+    pub fn comment<S: Into<String>>(comment: S) -> Self {
+        Self {
+            comment: Some(comment.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the event id, sent back by the browser in the
+    /// `Last-Event-ID` header when it reconnects.
+    ///
+    /// Line breaks are not allowed and are removed.
+    pub fn id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = Some(single_line(id.into()));
+        self
+    }
+
+    /// Sets the event name, dispatched to the matching `addEventListener` on the client.
+    ///
+    /// Line breaks are not allowed and are removed.
+    pub fn event<S: Into<String>>(mut self, event: S) -> Self {
+        self.event = Some(single_line(event.into()));
+        self
+    }
+
+    /// Sets how long the client waits before reconnecting when the stream is interrupted.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        if let Some(ref comment) = self.comment {
+            for line in lines(comment) {
+                put_field(&mut buf, "", line);
+            }
+        }
+
+        if let Some(ref event) = self.event {
+            put_field(&mut buf, "event", event);
+        }
+
+        if let Some(ref id) = self.id {
+            put_field(&mut buf, "id", id);
+        }
+
+        if let Some(retry) = self.retry {
+            put_field(&mut buf, "retry", &retry.as_millis().to_string());
+        }
+
+        if let Some(ref data) = self.data {
+            for line in lines(data) {
+                put_field(&mut buf, "data", line);
+            }
+        }
+
+        buf.put_u8(b'\n');
+        buf.freeze()
+    }
+}
+
+/// Splits `s` at every line break the client recognizes: `\r\n`, `\r` and `\n`.
+fn lines(s: &str) -> impl Iterator<Item = &str> {
+    s.split("\r\n").flat_map(|line| line.split(['\r', '\n']))
+}
+
+fn single_line(s: String) -> String {
+    s.replace(['\r', '\n'], "")
+}
+
+fn put_field(buf: &mut BytesMut, name: &str, value: &str) {
+    buf.put_slice(name.as_bytes());
+    buf.put_slice(b": ");
+    buf.put_slice(value.as_bytes());
+    buf.put_u8(b'\n');
+}
+
+/// A Server-Sent Events response, streaming [`Event`]s to the client.
+///
+/// When keep-alive is enabled, a comment is sent every time the stream
+/// stays idle for the given interval, so that proxies don't close the connection.
+///
+/// Clients reconnecting after an interruption send the id of the last
+/// event they received, available through [`Request::last_event_id`](crate::Request::last_event_id),
+/// which can be used to resume the stream where it was left.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures_util::{StreamExt, stream};
+/// use http::{Error, Event, Request, Response, Sse};
+/// use std::time::Duration;
+///
+/// async fn lobby(req: Request) -> Result<Response, Error> {
+///     let from = req.last_event_id().and_then(|id| id.parse::<u64>().ok()).unwrap_or(0);
+///     let events = stream::iter(from..).map(|n| Event::data(format!("update {}", n)).id(n.to_string()));
+///
+///     Ok(Sse::new(events).keep_alive(Duration::from_secs(15)).into())
+/// }
+/// ```
+pub struct Sse<S> {
+    stream: S,
+    keep_alive: Option<Duration>,
+}
+
+impl<S> Sse<S>
+where
+    S: Stream<Item = Event> + Send + 'static,
+{
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            keep_alive: None,
+        }
+    }
+
+    /// Sends a keep-alive comment whenever no event was sent for `interval`.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+}
+
+impl<S> From<Sse<S>> for Response
+where
+    S: Stream<Item = Event> + Send + 'static,
+{
+    fn from(sse: Sse<S>) -> Self {
+        let body = SseStream {
+            stream: Box::pin(sse.stream),
+            keep_alive: sse
+                .keep_alive
+                .map(|interval| (interval, Box::pin(tokio::time::sleep(interval)))),
+        };
+
+        Response::empty()
+            .header(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"))
+            .header(CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+            .stream(body)
+    }
+}
+
+/// Serializes events and interleaves keep-alive comments.
+struct SseStream<S> {
+    stream: Pin<Box<S>>,
+    keep_alive: Option<(Duration, Pin<Box<Sleep>>)>,
+}
+
+impl<S: Stream<Item = Event>> Stream for SseStream<S> {
+    type Item = Result<Bytes, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(event) = self.stream.as_mut().poll_next(cx) {
+            if let Some((interval, ref mut sleep)) = self.keep_alive {
+                sleep.as_mut().reset(Instant::now() + interval);
+            }
+
+            return Poll::Ready(event.map(|e| Ok(e.to_bytes())));
+        }
+
+        if let Some((interval, ref mut sleep)) = self.keep_alive
+            && sleep.as_mut().poll(cx).is_ready()
+        {
+            sleep.as_mut().reset(Instant::now() + interval);
+            return Poll::Ready(Some(Ok(Bytes::from_static(b":\n\n"))));
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{StreamExt, stream};
+
+    fn encode(event: Event) -> String {
+        String::from_utf8(event.to_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn encodes_events() {
+        let event = Event::data("{}")
+            .event("balance")
+            .id("42")
+            .retry(Duration::from_secs(5));
+
+        assert_eq!(
+            encode(event),
+            "event: balance\nid: 42\nretry: 5000\ndata: {}\n\n"
+        );
+    }
+
+    #[test]
+    fn splits_data_at_every_line_break() {
+        assert_eq!(
+            encode(Event::data("a\r\nb\nc\rinjected: 0")),
+            "data: a\ndata: b\ndata: c\ndata: injected: 0\n\n"
+        );
+    }
+
+    #[test]
+    fn splits_comments_at_every_line_break() {
+        assert_eq!(encode(Event::comment("a\rid: 1")), ": a\n: id: 1\n\n");
+    }
+
+    #[test]
+    fn strips_line_breaks_from_single_line_fields() {
+        assert_eq!(
+            encode(Event::default().event("a\rdata: b").id("1\n2")),
+            "event: adata: b\nid: 12\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_idle_streams_alive() {
+        let interval = Duration::from_millis(20);
+
+        let mut body = SseStream {
+            stream: Box::pin(stream::iter([Event::data("first")]).chain(stream::pending())),
+            keep_alive: Some((interval, Box::pin(tokio::time::sleep(interval)))),
+        };
+
+        let first = body.next().await.unwrap().unwrap();
+        assert_eq!(first, Bytes::from_static(b"data: first\n\n"));
+
+        let ping = tokio::time::timeout(interval * 10, body.next()).await;
+        assert_eq!(
+            ping.unwrap().unwrap().unwrap(),
+            Bytes::from_static(b":\n\n")
+        );
+    }
+}
@@ -0,0 +1,476 @@
+use crate::{
+    compression,
+    conditional::{Conditions, ETag, precondition_failed},
+    error::Error,
+    request::Request,
+    response::Response,
+    traits::ApiHandler,
+};
+use async_trait::async_trait;
+use hyper::{
+    Method, StatusCode,
+    header::{
+        ACCEPT_RANGES, ALLOW, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+        HeaderMap, HeaderValue, IF_RANGE, LAST_MODIFIED, RANGE, VARY,
+    },
+};
+use percent_encoding::percent_decode_str;
+use std::{
+    fs::Metadata,
+    io::SeekFrom,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+
+/// Precompressed variants looked up next to a file, in order of preference.
+const ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// Serves the files of a directory, such as the build output of a frontend.
+///
+/// The request path is mapped to a file inside the root directory,
+/// serving `index.html` for directories. Responses carry the MIME type
+/// guessed from the extension, `ETag` and `Last-Modified` validators,
+/// and support conditional and single-range requests.
+///
+/// When the client accepts it and a `.br` or `.gz` file exists next to
+/// the requested one, the precompressed variant is sent instead.
+///
+/// # Example
+///
+/// Serve the API and the `www` build from the same app, letting
+/// the frontend router handle unknown paths:
+///
+/// ```no_run
+/// use http::{Router, StaticFiles};
+///
+/// let router = Router::new()
+///     // .post("/session", ...)
+///     .fallback(StaticFiles::new("www/dist").spa_fallback(true));
+/// ```
+pub struct StaticFiles {
+    root: PathBuf,
+    prefix: String,
+    index: String,
+    spa: bool,
+    precompressed: bool,
+}
+
+/// A file picked to answer a request.
+struct Resolved {
+    path: PathBuf,
+    meta: Metadata,
+    content_type: HeaderValue,
+    encoding: Option<&'static str>,
+}
+
+impl StaticFiles {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            prefix: String::new(),
+            index: "index.html".to_string(),
+            spa: false,
+            precompressed: true,
+        }
+    }
+
+    /// Removes `prefix` from the request path before looking up the file,
+    /// for when the files are mounted under a sub path, e.g. `/assets`.
+    pub fn strip_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets the file served for directories, `index.html` by default.
+    pub fn index<S: Into<String>>(mut self, index: S) -> Self {
+        self.index = index.into();
+        self
+    }
+
+    /// Serves the root index file for missing paths without an extension,
+    /// so that a single page application can handle its own routes.
+    pub fn spa_fallback(mut self, value: bool) -> Self {
+        self.spa = value;
+        self
+    }
+
+    /// Whether to look for `.br` and `.gz` variants of the files, enabled by default.
+    pub fn precompressed(mut self, value: bool) -> Self {
+        self.precompressed = value;
+        self
+    }
+
+    /// Maps the request path to a path inside the root directory.
+    ///
+    /// Returns `None` if the path tries to escape the root, including through
+    /// encoded separators like `..%2F` that would make a segment a path of its own.
+    fn path_of(&self, uri_path: &str) -> Option<PathBuf> {
+        let path = uri_path.strip_prefix(&self.prefix)?;
+
+        // `/static` mounts `/static/a.txt`, not `/staticassets/a.txt`
+        if !path.is_empty() && !path.starts_with('/') {
+            return None;
+        }
+
+        let mut resolved = self.root.clone();
+
+        for segment in path.split('/') {
+            let segment = percent_decode_str(segment).decode_utf8().ok()?;
+
+            match segment.as_ref() {
+                "" | "." => continue,
+                ".." => return None,
+                s if s.contains(['/', '\\', '\0']) => return None,
+                s if !matches!(Path::new(s).components().next(), Some(Component::Normal(_))) => {
+                    return None;
+                }
+                s => resolved.push(s),
+            }
+        }
+
+        Some(resolved)
+    }
+
+    /// Whether `path` is still inside the root once symbolic links are resolved.
+    async fn is_inside_root(&self, path: &Path) -> bool {
+        let (Ok(root), Ok(path)) = (
+            tokio::fs::canonicalize(&self.root).await,
+            tokio::fs::canonicalize(path).await,
+        ) else {
+            return false;
+        };
+
+        path.starts_with(root)
+    }
+
+    async fn find(&self, mut path: PathBuf) -> Option<(PathBuf, Metadata)> {
+        let mut meta = tokio::fs::metadata(&path).await.ok()?;
+
+        if meta.is_dir() {
+            path.push(&self.index);
+            meta = tokio::fs::metadata(&path).await.ok()?;
+        }
+
+        if !meta.is_file() || !self.is_inside_root(&path).await {
+            return None;
+        }
+
+        Some((path, meta))
+    }
+
+    async fn resolve(&self, req: &Request) -> Option<Resolved> {
+        let requested = self.path_of(req.uri().path())?;
+
+        let (path, meta) = match self.find(requested.clone()).await {
+            Some(found) => found,
+            None if self.spa && requested.extension().is_none() => {
+                self.find(self.root.join(&self.index)).await?
+            }
+            None => return None,
+        };
+
+        let content_type = mime_guess::from_path(&path)
+            .first_raw()
+            .map(HeaderValue::from_static)
+            .unwrap_or_else(|| HeaderValue::from_static("application/octet-stream"));
+
+        if self.precompressed {
+            for (encoding, ext) in ENCODINGS {
+                if compression::quality(req.headers(), encoding) <= 0.0 {
+                    continue;
+                }
+
+                let mut variant = path.clone().into_os_string();
+                variant.push(".");
+                variant.push(ext);
+
+                if let Some((path, meta)) = self.find(variant.into()).await {
+                    return Some(Resolved {
+                        path,
+                        meta,
+                        content_type,
+                        encoding: Some(encoding),
+                    });
+                }
+            }
+        }
+
+        Some(Resolved {
+            path,
+            meta,
+            content_type,
+            encoding: None,
+        })
+    }
+}
+
+#[async_trait]
+impl ApiHandler for StaticFiles {
+    async fn incoming(&self, req: Request) -> Result<Response, Error> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Ok(Response::empty()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(ALLOW, HeaderValue::from_static("GET, HEAD")));
+        }
+
+        let file = self
+            .resolve(&req)
+            .await
+            .ok_or_else(|| Error::not_found("File not found"))?;
+
+        let mut res = serve(&req, &file).await?;
+
+        if self.precompressed {
+            res.headers_mut()
+                .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+        }
+
+        Ok(res)
+    }
+}
+
+async fn serve(req: &Request, file: &Resolved) -> Result<Response, Error> {
+    let len = file.meta.len();
+    let modified = file.meta.modified().ok();
+    let etag = etag(len, modified, file.encoding);
+    let tag = HeaderValue::from(etag.clone());
+
+    let mut res = Response::empty()
+        .header(CONTENT_TYPE, file.content_type.clone())
+        .header(ACCEPT_RANGES, HeaderValue::from_static("bytes"))
+        .header(ETAG, tag.clone());
+
+    if let Some(modified) = modified {
+        res = res.header(
+            LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(modified)).unwrap(),
+        );
+    }
+
+    if let Some(encoding) = file.encoding {
+        res = res.header(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+
+    match Conditions::new(req.headers()).evaluate(req.method(), true, Some(&etag), modified) {
+        Some(StatusCode::NOT_MODIFIED) => return Ok(res.status(StatusCode::NOT_MODIFIED)),
+        Some(_) => return Err(precondition_failed()),
+        None => {}
+    }
+
+    let (start, end) = match range(req.headers(), &tag, modified, len) {
+        Some(Ok((start, end))) => {
+            res = res.status(StatusCode::PARTIAL_CONTENT).header(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, len)).unwrap(),
+            );
+
+            (start, end)
+        }
+
+        Some(Err(())) => {
+            return Ok(Response::empty()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(
+                    CONTENT_RANGE,
+                    HeaderValue::from_str(&format!("bytes */{}", len)).unwrap(),
+                ));
+        }
+
+        None if len == 0 => return Ok(res.header(CONTENT_LENGTH, HeaderValue::from(0))),
+        None => (0, len - 1),
+    };
+
+    let count = end - start + 1;
+    res = res.header(CONTENT_LENGTH, HeaderValue::from(count));
+
+    if req.method() == Method::HEAD {
+        return Ok(res);
+    }
+
+    let mut handle = File::open(&file.path).await?;
+    handle.seek(SeekFrom::Start(start)).await?;
+
+    Ok(res.stream(ReaderStream::new(handle.take(count))))
+}
+
+/// A strong validator derived from the size and modification time of the file.
+fn etag(len: u64, modified: Option<SystemTime>, encoding: Option<&str>) -> ETag {
+    let mtime = modified
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    match encoding {
+        Some(encoding) => ETag::strong(format!("{:x}-{:x}-{}", len, mtime, encoding)),
+        None => ETag::strong(format!("{:x}-{:x}", len, mtime)),
+    }
+}
+
+/// Parses a single `bytes` range into inclusive bounds.
+///
+/// Returns `None` when the whole file should be sent (no range, multiple ranges,
+/// or an outdated `If-Range`), and `Some(Err(()))` when the range can't be satisfied.
+fn range(
+    headers: &HeaderMap,
+    etag: &HeaderValue,
+    modified: Option<SystemTime>,
+    len: u64,
+) -> Option<Result<(u64, u64), ()>> {
+    let spec = headers.get(RANGE)?.to_str().ok()?.strip_prefix("bytes=")?;
+
+    if let Some(if_range) = headers.get(IF_RANGE) {
+        let matches = if_range == etag
+            || if_range
+                .to_str()
+                .ok()
+                .and_then(|v| httpdate::parse_http_date(v).ok())
+                .zip(modified)
+                .is_some_and(|(date, modified)| httpdate::HttpDate::from(date) == modified.into());
+
+        if !matches {
+            return None;
+        }
+    }
+
+    if spec.contains(',') {
+        return None;
+    }
+
+    let (start, end) = spec.trim().split_once('-')?;
+
+    let bounds = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=-500, the last 500 bytes
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => {
+            Some((len.saturating_sub(suffix), len.saturating_sub(1)))
+        }
+        // bytes=500-
+        (Ok(start), Err(_)) if end.is_empty() => Some((start, len.saturating_sub(1))),
+        // bytes=500-999
+        (Ok(start), Ok(end)) if start <= end => Some((start, end.min(len.saturating_sub(1)))),
+        _ => return None,
+    };
+
+    Some(bounds.filter(|(start, _)| *start < len).ok_or(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestClient;
+
+    /// A root directory holding `index.html`, next to a `secret.txt` outside of it.
+    fn scratch() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("www");
+
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("index.html"), "<h1>casino</h1>").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+
+        (dir, root)
+    }
+
+    #[tokio::test]
+    async fn serves_files_of_the_root() {
+        let (_dir, root) = scratch();
+        let client = TestClient::new(StaticFiles::new(root));
+
+        let res = client.get("/index.html").send().await;
+
+        res.assert_status(StatusCode::OK)
+            .assert_header("content-type", "text/html");
+        assert_eq!(res.text(), "<h1>casino</h1>");
+    }
+
+    #[tokio::test]
+    async fn revalidates_with_the_conditions_of_the_request() {
+        let (_dir, root) = scratch();
+        let client = TestClient::new(StaticFiles::new(root));
+
+        let res = client.get("/index.html").send().await;
+        let etag = res.header("etag").unwrap().to_string();
+        let modified = res.header("last-modified").unwrap().to_string();
+
+        let res = client
+            .get("/index.html")
+            .header("if-none-match", format!("W/{}", etag))
+            .send()
+            .await;
+        res.assert_status(StatusCode::NOT_MODIFIED);
+
+        // If-None-Match takes precedence over If-Modified-Since
+        let res = client
+            .get("/index.html")
+            .header("if-none-match", "\"stale\"")
+            .header("if-modified-since", modified)
+            .send()
+            .await;
+        res.assert_status(StatusCode::OK);
+
+        let res = client
+            .get("/index.html")
+            .header("if-match", "\"stale\"")
+            .send()
+            .await;
+        res.assert_status(StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn strips_the_prefix_at_a_segment_boundary() {
+        let (_dir, root) = scratch();
+        std::fs::create_dir(root.join("assets")).unwrap();
+        std::fs::write(root.join("assets").join("a.txt"), "a").unwrap();
+
+        let client = TestClient::new(StaticFiles::new(root).strip_prefix("/static/"));
+
+        let res = client.get("/static/assets/a.txt").send().await;
+        res.assert_status(StatusCode::OK);
+
+        let res = client.get("/staticassets/a.txt").send().await;
+        res.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn rejects_encoded_parent_segments() {
+        let (_dir, root) = scratch();
+        let client = TestClient::new(StaticFiles::new(root));
+
+        for path in [
+            "/..%2Fsecret.txt",
+            "/%2E%2E/secret.txt",
+            "/a%2F..%2F..%2Fsecret.txt",
+        ] {
+            let res = client.get(path).send().await;
+            res.assert_status(StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_encoded_absolute_paths() {
+        let (dir, root) = scratch();
+        let client = TestClient::new(StaticFiles::new(root));
+
+        let secret = dir.path().join("secret.txt");
+        let path = secret.to_str().unwrap().replace('/', "%2F");
+
+        let res = client.get(&format!("/{}", path)).send().await;
+        res.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn rejects_symlinks_out_of_the_root() {
+        let (dir, root) = scratch();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), root.join("link.txt")).unwrap();
+
+        let client = TestClient::new(StaticFiles::new(root));
+
+        let res = client.get("/link.txt").send().await;
+        res.assert_status(StatusCode::NOT_FOUND);
+    }
+}
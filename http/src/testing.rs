@@ -0,0 +1,456 @@
+//! Calling handlers in-process, without binding a port.
+//!
+//! A [`TestClient`] feeds synthetic requests straight into any [`ApiHandler`]
+//! (a [`Router`](crate::Router), a single route, a middleware stack...)
+//! and buffers the whole response into a [`TestResponse`] that can be inspected.
+//!
+//! Errors returned by the handler are rendered like the [`App`](crate::App)
+//! would, so a failing route yields its error status and JSON body,
+//! and conditional requests are answered with `304` or `412` the same way.
+//! App-level settings such as CORS or compression are not applied.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use http::{Error, Request, Response, Router, testing::TestClient};
+//! use hyper::StatusCode;
+//! use serde_json::{Value, json};
+//!
+//! async fn echo(req: Request) -> Result<Response, Error> {
+//!     let body = req.json::<Value>().await?;
+//!     Response::empty().status(StatusCode::CREATED).body(body)
+//! }
+//!
+//! #[tokio::test]
+//! async fn echoes_the_body() {
+//!     let client = TestClient::new(Router::new().post("/echo", echo));
+//!
+//!     let res = client
+//!         .post("/echo")
+//!         .json(&json!({ "hello": "world" }))
+//!         .send()
+//!         .await;
+//!
+//!     res.assert_status(StatusCode::CREATED);
+//!     assert_eq!(res.json::<Value>()["hello"], "world");
+//! }
+//! ```
+
+use crate::{
+    body::DEFAULT_BODY_LIMIT, conditional::Conditions, format::Format, request::Request,
+    state::StateMap, traits::ApiHandler,
+};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    HeaderMap, Method, StatusCode,
+    header::{ACCEPT, CONTENT_TYPE, COOKIE, HeaderName, HeaderValue},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{net::SocketAddr, sync::Arc};
+
+/// Sends requests to a handler in-process.
+pub struct TestClient<H> {
+    handler: H,
+    state: Arc<StateMap>,
+}
+
+impl<H: ApiHandler> TestClient<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            state: Arc::default(),
+        }
+    }
+
+    /// Registers a value shared by every request, like [`App::state`](crate::App::state).
+    pub fn state<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        Arc::make_mut(&mut self.state).insert(value);
+        self
+    }
+
+    /// Starts building a request to `path`, which can include a query string.
+    pub fn request(&self, method: Method, path: &str) -> TestRequest<'_, H> {
+        TestRequest {
+            client: self,
+            builder: hyper::Request::builder().method(method).uri(path),
+            body: Bytes::new(),
+            body_limit: DEFAULT_BODY_LIMIT,
+            peer: None,
+        }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest<'_, H> {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest<'_, H> {
+        self.request(Method::POST, path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest<'_, H> {
+        self.request(Method::PUT, path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest<'_, H> {
+        self.request(Method::PATCH, path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest<'_, H> {
+        self.request(Method::DELETE, path)
+    }
+}
+
+/// A request being built, sent with [`TestRequest::send`].
+pub struct TestRequest<'a, H> {
+    client: &'a TestClient<H>,
+    builder: hyper::http::request::Builder,
+    body: Bytes,
+    body_limit: usize,
+    peer: Option<SocketAddr>,
+}
+
+impl<H: ApiHandler> TestRequest<'_, H> {
+    /// Adds a header.
+    ///
+    /// # Panics
+    ///
+    /// If the name or value isn't valid in a header.
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: TryInto<HeaderName>,
+        K::Error: std::fmt::Debug,
+        V: TryInto<HeaderValue>,
+        V::Error: std::fmt::Debug,
+    {
+        let name = name.try_into().expect("invalid header name");
+        let value = value.try_into().expect("invalid header value");
+
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    /// Adds a `name=value` pair to the `Cookie` header.
+    pub fn cookie(self, name: &str, value: &str) -> Self {
+        self.header(COOKIE, format!("{}={}", name, value))
+    }
+
+    /// Sets a raw body.
+    pub fn body<B: Into<Bytes>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Sets a plain text body.
+    pub fn text<T: Into<String>>(self, text: T) -> Self {
+        self.header(CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(text.into())
+    }
+
+    /// Serializes `payload` as the JSON body.
+    ///
+    /// # Panics
+    ///
+    /// If the payload can't be serialized.
+    pub fn json<B: Serialize>(self, payload: &B) -> Self {
+        let json = serde_json::to_vec(payload).expect("failed to serialize the JSON body");
+        self.header(CONTENT_TYPE, "application/json").body(json)
+    }
+
+    /// Serializes `payload` as the body in `format`, with the matching `Content-Type`.
+    ///
+    /// # Panics
+    ///
+    /// If the payload can't be serialized.
+    pub fn payload<B: Serialize>(self, format: Format, payload: &B) -> Self {
+        let bytes = format
+            .serialize(payload)
+            .expect("failed to serialize the body");
+
+        self.header(CONTENT_TYPE, format.content_type()).body(bytes)
+    }
+
+    /// Sets the `Accept` header to `format`.
+    pub fn accept(self, format: Format) -> Self {
+        self.header(ACCEPT, format.content_type())
+    }
+
+    /// Sets the body limit seen by the handler, [`DEFAULT_BODY_LIMIT`] by default.
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// Sets the address the request appears to come from, none by default.
+    pub fn peer_addr(mut self, peer: SocketAddr) -> Self {
+        self.peer = Some(peer);
+        self
+    }
+
+    /// Inserts a value in the request extensions, as a middleware would.
+    pub fn extension<T: Clone + Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.builder = self.builder.extension(value);
+        self
+    }
+
+    /// Runs the handler and reads the whole response.
+    ///
+    /// Never returns if the handler responds with an endless stream, such as [`Sse`](crate::Sse).
+    ///
+    /// # Panics
+    ///
+    /// If the request is invalid (e.g. a malformed path) or the response body fails.
+    pub async fn send(self) -> TestResponse {
+        let req = self
+            .builder
+            .body(Full::new(self.body))
+            .expect("invalid test request");
+
+        let mut req = Request::from(req);
+        req.set_body_limit(self.body_limit);
+        req.set_state(self.client.state.clone());
+
+        if let Some(peer) = self.peer {
+            req.set_peer_addr(peer);
+        }
+
+        let method = req.method().clone();
+        let conditions = Conditions::new(req.headers());
+
+        let res = match self.client.handler.incoming(req).await {
+            Ok(res) => res,
+            Err(err) => err.into(),
+        };
+
+        let res = conditions.apply(&method, res);
+
+        let (parts, body) = hyper::Response::from(res).into_parts();
+        let body = body
+            .collect()
+            .await
+            .expect("failed to read the response body")
+            .to_bytes();
+
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        }
+    }
+}
+
+/// A fully buffered response.
+#[derive(Debug)]
+pub struct TestResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestResponse {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The first value of the header `name`, if it's present and valid text.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn bytes(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// # Panics
+    ///
+    /// If the body isn't valid UTF-8.
+    pub fn text(&self) -> &str {
+        std::str::from_utf8(&self.body).expect("response body is not valid UTF-8")
+    }
+
+    /// # Panics
+    ///
+    /// If the body isn't valid JSON for `T`, showing the body.
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| {
+            panic!(
+                "response body is not valid JSON ({}): {}",
+                e,
+                String::from_utf8_lossy(&self.body)
+            )
+        })
+    }
+
+    /// Deserializes the body in the format given by its `Content-Type`, JSON if there's none.
+    ///
+    /// # Panics
+    ///
+    /// If the content type isn't a supported [`Format`] or the body isn't valid for `T`.
+    pub fn payload<T: DeserializeOwned>(&self) -> T {
+        let format = match self.header("content-type") {
+            Some(value) => Format::from_content_type(value)
+                .unwrap_or_else(|| panic!("unsupported response content type: {}", value)),
+            None => Format::Json,
+        };
+
+        format
+            .deserialize(&self.body)
+            .unwrap_or_else(|e| panic!("response body is not valid {:?}: {}", format, e))
+    }
+
+    /// Panics if the status isn't `expected`, showing the body to help debugging.
+    #[track_caller]
+    pub fn assert_status(&self, expected: StatusCode) -> &Self {
+        assert_eq!(
+            self.status,
+            expected,
+            "unexpected status, body: {}",
+            String::from_utf8_lossy(&self.body)
+        );
+
+        self
+    }
+
+    /// Panics if the header `name` isn't `expected`.
+    #[track_caller]
+    pub fn assert_header(&self, name: &str, expected: &str) -> &Self {
+        assert_eq!(self.header(name), Some(expected), "header {}", name);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, Response, Router, cookie::Cookie};
+    use serde_json::{Value, json};
+
+    async fn echo(req: Request) -> Result<Response, Error> {
+        let body = req.json::<Value>().await?;
+        Response::empty().status(StatusCode::CREATED).body(body)
+    }
+
+    async fn echo_payload(req: Request) -> Result<Response, Error> {
+        let format = req.preferred_format();
+        let body = req.payload::<Value>().await?;
+        Response::empty().body_as(format, body)
+    }
+
+    async fn whoami(req: Request) -> Result<Response, Error> {
+        let peer = req.peer_addr().map(|peer| peer.to_string());
+        let flavor = req.cookies().get("flavor").map(|c| c.value().to_string());
+        let greeting = req.state::<String>().map(|s| s.to_string());
+
+        Response::empty()
+            .header(HeaderName::from_static("x-seen"), HeaderValue::from(1))
+            .body(json!({ "peer": peer, "flavor": flavor, "greeting": greeting }))
+    }
+
+    fn client() -> TestClient<Router> {
+        TestClient::new(
+            Router::new()
+                .post("/echo", echo)
+                .post("/payload", echo_payload)
+                .get("/whoami", whoami),
+        )
+        .state("hello".to_string())
+    }
+
+    #[tokio::test]
+    async fn sends_json_and_reads_the_response() {
+        let res = client()
+            .post("/echo")
+            .json(&json!({ "chips": 100 }))
+            .send()
+            .await;
+
+        res.assert_status(StatusCode::CREATED)
+            .assert_header("content-type", "application/json");
+        assert_eq!(res.json::<Value>(), json!({ "chips": 100 }));
+    }
+
+    #[tokio::test]
+    async fn renders_handler_errors() {
+        let res = client().post("/echo").text("not json").send().await;
+
+        res.assert_status(StatusCode::BAD_REQUEST);
+        assert!(res.json::<Value>()["code"].is_string());
+    }
+
+    #[tokio::test]
+    async fn renders_unknown_routes() {
+        client()
+            .get("/nowhere")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn passes_peer_cookies_and_state() {
+        let res = client()
+            .get("/whoami")
+            .peer_addr("10.0.0.7:4000".parse().unwrap())
+            .cookie("flavor", "vanilla")
+            .send()
+            .await;
+
+        res.assert_status(StatusCode::OK)
+            .assert_header("x-seen", "1");
+        assert_eq!(
+            res.json::<Value>(),
+            json!({ "peer": "10.0.0.7:4000", "flavor": "vanilla", "greeting": "hello" })
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_payloads_in_the_negotiated_format() {
+        let res = client()
+            .post("/payload")
+            .payload(Format::Cbor, &json!({ "chips": 5 }))
+            .accept(Format::MessagePack)
+            .send()
+            .await;
+
+        res.assert_status(StatusCode::OK)
+            .assert_header("content-type", "application/msgpack");
+        assert_eq!(res.payload::<Value>(), json!({ "chips": 5 }));
+    }
+
+    #[tokio::test]
+    async fn answers_conditional_requests() {
+        let handler = |_: Request| async {
+            Ok(Response::empty()
+                .etag(crate::ETag::strong("v1"))
+                .text("table"))
+        };
+        let client = TestClient::new(Router::new().get("/table", handler));
+
+        client
+            .get("/table")
+            .header("if-none-match", "\"v1\"")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_MODIFIED);
+
+        client
+            .get("/table")
+            .header("if-match", "\"v0\"")
+            .send()
+            .await
+            .assert_status(StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn keeps_set_cookie_headers() {
+        let handler =
+            |_: Request| async { Ok(Response::empty().cookie(Cookie::new("session", "abc"))) };
+        let res = TestClient::new(handler).get("/").send().await;
+
+        assert_eq!(res.header("set-cookie"), Some("session=abc"));
+    }
+}
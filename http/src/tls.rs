@@ -0,0 +1,327 @@
+use rustls::{
+    ServerConfig,
+    crypto::{CryptoProvider, ring},
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use traccia::error;
+
+/// TLS configuration of an [`App`](crate::App).
+///
+/// Certificates and private keys are loaded from PEM files. When reloading
+/// is enabled, the files are checked periodically and, if they changed,
+/// loaded again without restarting the app: new connections use the new
+/// certificate, while the open ones keep the one they negotiated.
+///
+/// The ALPN protocols advertised to clients follow the [`Protocol`](crate::Protocol)
+/// of the app, so `h2` is only offered when HTTP/2 is enabled.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{App, Protocol, Router, Tls};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() -> tokio::io::Result<()> {
+///     let tls = Tls::from_pem_files("cert.pem", "key.pem")?
+///         .reload_every(Duration::from_secs(60));
+///
+///     App::new("0.0.0.0:443".parse().unwrap())
+///         .await?
+///         .tls(tls)
+///         .protocol(Protocol::Auto)
+///         .run(Router::new())
+///         .await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Tls {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    resolver: Arc<Resolver>,
+    reload_interval: Option<Duration>,
+}
+
+impl Tls {
+    /// Loads a certificate chain and its private key from PEM files.
+    ///
+    /// The certificate file can hold the whole chain, leaf first.
+    /// The key can be in PKCS#1, PKCS#8 or SEC1 format.
+    pub fn from_pem_files<C: AsRef<Path>, K: AsRef<Path>>(cert: C, key: K) -> io::Result<Self> {
+        let cert_path = cert.as_ref().to_path_buf();
+        let key_path = key.as_ref().to_path_buf();
+        let key = load(&cert_path, &key_path)?;
+
+        Ok(Self {
+            cert_path,
+            key_path,
+            resolver: Arc::new(Resolver {
+                key: RwLock::new(Arc::new(key)),
+            }),
+            reload_interval: None,
+        })
+    }
+
+    /// Checks the certificate and key files for changes every `interval`,
+    /// reloading them when they are modified.
+    ///
+    /// If the new files can't be loaded, or the key doesn't belong to the certificate
+    /// (e.g. when caught between the two writes of a rotation), the previous certificate
+    /// stays in use and loading is tried again at the next check.
+    pub fn reload_every(mut self, interval: Duration) -> Self {
+        self.reload_interval = Some(interval);
+        self
+    }
+
+    /// Builds the acceptor used to perform handshakes, advertising `alpn` protocols.
+    pub(crate) fn acceptor(&self, alpn: Vec<Vec<u8>>) -> io::Result<TlsAcceptor> {
+        let mut config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_no_client_auth()
+            .with_cert_resolver(self.resolver.clone());
+
+        config.alpn_protocols = alpn;
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+
+    /// Starts watching the certificate files, if reloading is enabled.
+    pub(crate) fn spawn_reloader(&self) -> Option<JoinHandle<()>> {
+        let interval = self.reload_interval?;
+        let cert_path = self.cert_path.clone();
+        let key_path = self.key_path.clone();
+        let resolver = self.resolver.clone();
+
+        Some(tokio::spawn(async move {
+            let mut last = modified(&cert_path, &key_path).await;
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                ticker.tick().await;
+
+                let current = modified(&cert_path, &key_path).await;
+
+                if current == last {
+                    continue;
+                }
+
+                match load(&cert_path, &key_path) {
+                    Ok(key) => {
+                        *resolver.key.write().unwrap() = Arc::new(key);
+                        last = current;
+                    }
+                    Err(e) => error!("Failed to reload TLS certificate: {}", e),
+                }
+            }
+        }))
+    }
+}
+
+/// Hands out the current certificate, which can be swapped at any time.
+#[derive(Debug)]
+struct Resolver {
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.key.read().unwrap().clone())
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Loads the certificate chain and its key, checking they go together.
+fn load(cert_path: &Path, key_path: &Path) -> io::Result<CertifiedKey> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .map_err(invalid)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid)?;
+
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no certificate found in {}", cert_path.display()),
+        ));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(invalid)?;
+    let key = provider()
+        .key_provider
+        .load_private_key(key)
+        .map_err(io::Error::other)?;
+
+    let certified = CertifiedKey::new(certs, key);
+    certified.keys_match().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} doesn't match {}: {}",
+                key_path.display(),
+                cert_path.display(),
+                e
+            ),
+        )
+    })?;
+
+    Ok(certified)
+}
+
+/// Last modification times of the certificate and key files.
+async fn modified(cert_path: &Path, key_path: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: PathBuf| async move {
+        tokio::fs::metadata(path)
+            .await
+            .and_then(|m| m.modified())
+            .ok()
+    };
+
+    (
+        modified(cert_path.to_path_buf()).await,
+        modified(key_path.to_path_buf()).await,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Protocol;
+    use rustls::{ClientConfig, RootCertStore, pki_types::ServerName};
+    use tokio_rustls::TlsConnector;
+
+    fn generate() -> rcgen::CertifiedKey {
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap()
+    }
+
+    /// A certificate for `localhost` written to a temporary directory, and the connector trusting it.
+    fn self_signed() -> (tempfile::TempDir, Tls, RootCertStore) {
+        let generated = generate();
+        let dir = tempfile::tempdir().unwrap();
+
+        let cert = dir.path().join("cert.pem");
+        let key = dir.path().join("key.pem");
+        std::fs::write(&cert, generated.cert.pem()).unwrap();
+        std::fs::write(&key, generated.key_pair.serialize_pem()).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(generated.cert.der().clone()).unwrap();
+
+        (dir, Tls::from_pem_files(cert, key).unwrap(), roots)
+    }
+
+    /// Performs a handshake over an in-memory stream, returning the negotiated protocol.
+    async fn handshake(
+        tls: &Tls,
+        roots: RootCertStore,
+        protocol: Protocol,
+        offered: &[&[u8]],
+    ) -> io::Result<Option<Vec<u8>>> {
+        let acceptor = tls.acceptor(protocol.alpn())?;
+
+        let mut config = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = offered.iter().map(|p| p.to_vec()).collect();
+
+        let connector = TlsConnector::from(Arc::new(config));
+        let (client, server) = tokio::io::duplex(16 * 1024);
+        let name = ServerName::try_from("localhost").unwrap();
+
+        let (client, server) =
+            tokio::join!(connector.connect(name, client), acceptor.accept(server));
+
+        let server = server?;
+        let client = client?;
+
+        assert_eq!(
+            client.get_ref().1.alpn_protocol(),
+            server.get_ref().1.alpn_protocol()
+        );
+        Ok(server.get_ref().1.alpn_protocol().map(<[u8]>::to_vec))
+    }
+
+    #[tokio::test]
+    async fn negotiates_the_protocols_of_the_app() {
+        let both: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+        let cases = [
+            (Protocol::Http1, both, b"http/1.1".as_slice()),
+            (Protocol::Http2, both, b"h2"),
+            (Protocol::Auto, both, b"h2"),
+            (Protocol::Auto, &[b"http/1.1"], b"http/1.1"),
+        ];
+
+        for (protocol, offered, expected) in cases {
+            let (_dir, tls, roots) = self_signed();
+            let negotiated = handshake(&tls, roots, protocol, offered).await.unwrap();
+
+            assert_eq!(negotiated.as_deref(), Some(expected), "{:?}", protocol);
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_clients_without_a_common_protocol() {
+        let (_dir, tls, roots) = self_signed();
+
+        let result = handshake(&tls, roots, Protocol::Http2, &[b"http/1.1"]).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn accepts_clients_without_alpn() {
+        let (_dir, tls, roots) = self_signed();
+
+        let negotiated = handshake(&tls, roots, Protocol::Auto, &[]).await.unwrap();
+        assert_eq!(negotiated, None);
+    }
+
+    #[test]
+    fn rejects_keys_of_another_certificate() {
+        let (dir, _, _) = self_signed();
+        let key = dir.path().join("key.pem");
+        std::fs::write(&key, generate().key_pair.serialize_pem()).unwrap();
+
+        let err = Tls::from_pem_files(dir.path().join("cert.pem"), key).err();
+        assert_eq!(err.map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+
+    #[tokio::test]
+    async fn reloads_once_the_key_matches_the_certificate() {
+        let (dir, tls, _) = self_signed();
+        let tls = tls.reload_every(Duration::from_millis(20));
+        let reloader = tls.spawn_reloader().unwrap();
+
+        let current = || tls.resolver.key.read().unwrap().cert[0].clone();
+        let before = current();
+
+        // Halfway through a rotation, the new certificate is there but not its key yet
+        let rotated = generate();
+        std::fs::write(dir.path().join("cert.pem"), rotated.cert.pem()).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(current(), before);
+
+        std::fs::write(dir.path().join("key.pem"), rotated.key_pair.serialize_pem()).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(&current(), rotated.cert.der());
+
+        reloader.abort();
+    }
+}
@@ -1,7 +1,51 @@
-use crate::{request::Request, response::Response};
+use crate::{error::Error, middleware::Next, request::Request, response::Response};
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait ApiHandler: Send + Sync + 'static {
-    async fn incoming(&self, req: Request) -> Result<Response, hyper::Error>;
+    async fn incoming(&self, req: Request) -> Result<Response, Error>;
+}
+
+/// Allows plain async functions and closures to be used as handlers,
+/// which is mostly useful when registering routes on a [`Router`](crate::Router).
+#[async_trait]
+impl<F, Fut> ApiHandler for F
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+{
+    async fn incoming(&self, req: Request) -> Result<Response, Error> {
+        (self)(req).await
+    }
+}
+
+/// Wraps the request handling of an [`App`](crate::App).
+///
+/// A middleware receives the request before the handler does and can:
+/// - inspect or mutate the request, then pass it on with [`Next::run`]
+/// - short-circuit by returning a response without calling `next`
+/// - post-process the response returned by `next`
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Middleware, Next, Request, Response, async_trait};
+/// use std::time::Instant;
+///
+/// struct Timing;
+///
+/// #[async_trait]
+/// impl Middleware for Timing {
+///     async fn handle(&self, req: Request, next: Next<'_>) -> Result<Response, Error> {
+///         let start = Instant::now();
+///         let res = next.run(req).await?;
+///
+///         println!("took {:?}", start.elapsed());
+///         Ok(res)
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait Middleware: Send + Sync + 'static {
+    async fn handle(&self, req: Request, next: Next<'_>) -> Result<Response, Error>;
 }
@@ -0,0 +1,393 @@
+use crate::{error::Error, response::Response};
+use bytes::Bytes;
+use futures_util::{
+    Sink, SinkExt, Stream, StreamExt,
+    stream::{SplitSink, SplitStream},
+};
+use hyper::{
+    StatusCode,
+    header::{
+        CONNECTION, HeaderMap, HeaderValue, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
+        SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE,
+    },
+    upgrade::{OnUpgrade, Upgraded},
+};
+use hyper_util::rt::TokioIo;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{self, handshake::derive_accept_key, protocol::Role, protocol::WebSocketConfig},
+};
+use traccia::warn;
+
+pub use tungstenite::Error as WebSocketError;
+
+/// A future driving an upgraded connection, run by the connection task.
+pub(crate) type Session = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A message sent or received over a [`WebSocket`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Bytes),
+
+    /// Pings are answered automatically, they are only exposed for inspection
+    Ping(Bytes),
+    Pong(Bytes),
+
+    /// The peer closed the connection, optionally telling why
+    Close(Option<CloseFrame>),
+}
+
+/// Code and reason of a closed WebSocket connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// The purpose of the connection was fulfilled
+    pub const NORMAL: u16 = 1000;
+
+    /// The server is going down or the client navigated away
+    pub const GOING_AWAY: u16 = 1001;
+
+    /// The peer violated the protocol
+    pub const PROTOCOL_ERROR: u16 = 1002;
+
+    /// The peer sent a kind of data that can't be accepted, e.g. binary when only text is expected
+    pub const UNSUPPORTED: u16 = 1003;
+
+    /// The peer sent a message that violates the application policy
+    pub const POLICY: u16 = 1008;
+
+    /// The peer sent a message too big to process
+    pub const TOO_BIG: u16 = 1009;
+
+    /// An unexpected condition prevented the server from fulfilling the request
+    pub const ERROR: u16 = 1011;
+
+    pub fn new<S: Into<String>>(code: u16, reason: S) -> Self {
+        Self {
+            code,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl From<Message> for tungstenite::Message {
+    fn from(msg: Message) -> Self {
+        match msg {
+            Message::Text(text) => tungstenite::Message::text(text),
+            Message::Binary(data) => tungstenite::Message::Binary(data),
+            Message::Ping(data) => tungstenite::Message::Ping(data),
+            Message::Pong(data) => tungstenite::Message::Pong(data),
+            Message::Close(frame) => {
+                tungstenite::Message::Close(frame.map(|f| tungstenite::protocol::CloseFrame {
+                    code: f.code.into(),
+                    reason: f.reason.into(),
+                }))
+            }
+        }
+    }
+}
+
+impl Message {
+    /// Converts a received message, returning `None` for raw frames,
+    /// which are never produced while reading.
+    fn from_tungstenite(msg: tungstenite::Message) -> Option<Self> {
+        Some(match msg {
+            tungstenite::Message::Text(text) => Message::Text(text.to_string()),
+            tungstenite::Message::Binary(data) => Message::Binary(data),
+            tungstenite::Message::Ping(data) => Message::Ping(data),
+            tungstenite::Message::Pong(data) => Message::Pong(data),
+            tungstenite::Message::Close(frame) => Message::Close(frame.map(|f| CloseFrame {
+                code: f.code.into(),
+                reason: f.reason.to_string(),
+            })),
+            tungstenite::Message::Frame(_) => return None,
+        })
+    }
+}
+
+/// An accepted WebSocket upgrade request, obtained with [`Request::websocket`](crate::Request::websocket).
+///
+/// Answer the request with the response returned by [`WebSocketUpgrade::on_upgrade`]
+/// to complete the handshake. Only HTTP/1.1 connections can be upgraded.
+///
+/// # Example
+///
+/// ```no_run
+/// use http::{Error, Message, Request, Response};
+///
+/// async fn echo(mut req: Request) -> Result<Response, Error> {
+///     let upgrade = req.websocket()?;
+///
+///     Ok(upgrade.on_upgrade(|mut socket| async move {
+///         while let Some(Ok(msg)) = socket.recv().await {
+///             if let Message::Text(_) | Message::Binary(_) = msg {
+///                 if socket.send(msg).await.is_err() {
+///                     break;
+///                 }
+///             }
+///         }
+///     }))
+/// }
+/// ```
+pub struct WebSocketUpgrade {
+    on_upgrade: OnUpgrade,
+    key: HeaderValue,
+    requested: Vec<String>,
+    protocol: Option<String>,
+    config: WebSocketConfig,
+    sessions: Option<UnboundedSender<Session>>,
+}
+
+impl WebSocketUpgrade {
+    /// Validates the handshake headers of an upgrade request.
+    pub(crate) fn new(
+        headers: &HeaderMap,
+        on_upgrade: OnUpgrade,
+        sessions: Option<UnboundedSender<Session>>,
+    ) -> Result<Self, Error> {
+        let contains = |name, token: &str| {
+            headers.get_all(name).iter().any(|v| {
+                v.to_str()
+                    .is_ok_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            })
+        };
+
+        if !contains(CONNECTION, "upgrade") || !contains(UPGRADE, "websocket") {
+            return Err(Error::bad_request("Not a WebSocket upgrade request"));
+        }
+
+        if headers.get(SEC_WEBSOCKET_VERSION).map(|v| v.as_bytes()) != Some(b"13") {
+            return Err(Error::new(
+                StatusCode::UPGRADE_REQUIRED,
+                "unsupported_websocket_version",
+                "Only WebSocket version 13 is supported",
+            ));
+        }
+
+        let key = headers
+            .get(SEC_WEBSOCKET_KEY)
+            .cloned()
+            .ok_or_else(|| Error::bad_request("Missing Sec-WebSocket-Key header"))?;
+
+        let requested = headers
+            .get_all(SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+
+        Ok(Self {
+            on_upgrade,
+            key,
+            requested,
+            protocol: None,
+            config: WebSocketConfig::default(),
+            sessions,
+        })
+    }
+
+    /// Sets the subprotocols supported by the server, in order of preference.
+    ///
+    /// The first one that is also requested by the client is selected.
+    /// If there's none, the handshake proceeds without a subprotocol.
+    pub fn protocols<I, S>(mut self, supported: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.protocol = supported
+            .into_iter()
+            .find(|p| self.requested.iter().any(|r| r == p.as_ref()))
+            .map(|p| p.as_ref().to_string());
+
+        self
+    }
+
+    /// Sets the maximum size of an incoming message, 64 MiB by default.
+    pub fn max_message_size(mut self, max: usize) -> Self {
+        self.config = self.config.max_message_size(Some(max));
+        self
+    }
+
+    /// Completes the handshake, running `callback` with the socket
+    /// once the connection has been upgraded.
+    ///
+    /// The returned response must be sent back for the upgrade to happen.
+    pub fn on_upgrade<F, Fut>(self, callback: F) -> Response
+    where
+        F: FnOnce(WebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let accept = derive_accept_key(self.key.as_bytes());
+        let protocol = self.protocol.clone();
+        let config = self.config;
+        let on_upgrade = self.on_upgrade;
+
+        let session = Box::pin(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    warn!("WebSocket upgrade failed: {}", e);
+                    return;
+                }
+            };
+
+            let inner = WebSocketStream::from_raw_socket(
+                TokioIo::new(upgraded),
+                Role::Server,
+                Some(config),
+            )
+            .await;
+
+            callback(WebSocket { inner, protocol }).await;
+        });
+
+        // Prefer the connection task, so the socket is tracked like any other connection
+        if let Some(sessions) = self.sessions {
+            if let Err(rejected) = sessions.send(session) {
+                tokio::spawn(rejected.0);
+            }
+        } else {
+            tokio::spawn(session);
+        }
+
+        let mut res = Response::empty()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, HeaderValue::from_static("upgrade"))
+            .header(UPGRADE, HeaderValue::from_static("websocket"))
+            .header(
+                SEC_WEBSOCKET_ACCEPT,
+                HeaderValue::from_str(&accept).unwrap(),
+            );
+
+        if let Some(protocol) = self.protocol.and_then(|p| HeaderValue::from_str(&p).ok()) {
+            res = res.header(SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+
+        res
+    }
+}
+
+/// An upgraded WebSocket connection.
+///
+/// Besides [`WebSocket::recv`] and [`WebSocket::send`], it implements
+/// [`Stream`] and [`Sink`], and can be split into two halves to read
+/// and write from different tasks.
+pub struct WebSocket {
+    inner: WebSocketStream<TokioIo<Upgraded>>,
+    protocol: Option<String>,
+}
+
+impl WebSocket {
+    /// Waits for the next message.
+    ///
+    /// Returns `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Result<Message, WebSocketError>> {
+        self.next().await
+    }
+
+    pub async fn send(&mut self, msg: Message) -> Result<(), WebSocketError> {
+        SinkExt::send(self, msg).await
+    }
+
+    /// Sends a close frame and waits for the peer to acknowledge it.
+    pub async fn close(mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        self.send(Message::Close(Some(CloseFrame::new(code, reason))))
+            .await?;
+
+        // Drain until the peer answers with its own close frame
+        while let Some(msg) = self.recv().await {
+            msg?;
+        }
+
+        Ok(())
+    }
+
+    /// The subprotocol agreed during the handshake, if any.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    pub fn split(self) -> (WebSocketSender, WebSocketReceiver) {
+        let (sink, stream) = StreamExt::split(self);
+        (WebSocketSender(sink), WebSocketReceiver(stream))
+    }
+}
+
+impl Stream for WebSocket {
+    type Item = Result<Message, WebSocketError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.inner.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(msg))) => match Message::from_tungstenite(msg) {
+                    Some(msg) => return Poll::Ready(Some(Ok(msg))),
+                    None => continue,
+                },
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Sink<Message> for WebSocket {
+    type Error = WebSocketError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready_unpin(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        self.inner.start_send_unpin(item.into())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_flush_unpin(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_close_unpin(cx)
+    }
+}
+
+/// The writing half of a [`WebSocket`].
+pub struct WebSocketSender(SplitSink<WebSocket, Message>);
+
+impl WebSocketSender {
+    pub async fn send(&mut self, msg: Message) -> Result<(), WebSocketError> {
+        self.0.send(msg).await
+    }
+
+    /// Sends a close frame, the reading half will end once the peer acknowledges it.
+    pub async fn close(mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        self.0
+            .send(Message::Close(Some(CloseFrame::new(code, reason))))
+            .await
+    }
+}
+
+/// The reading half of a [`WebSocket`].
+pub struct WebSocketReceiver(SplitStream<WebSocket>);
+
+impl WebSocketReceiver {
+    /// Waits for the next message.
+    ///
+    /// Returns `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Result<Message, WebSocketError>> {
+        self.0.next().await
+    }
+}
@@ -6,7 +6,7 @@ use mini_moka::sync::ConcurrentCacheExt;
 use std::{net::SocketAddr, str::FromStr};
 use tokio::io;
 
-use crate::RELAYS;
+use crate::Relays;
 
 pub struct ClearCommand;
 
@@ -27,7 +27,15 @@ impl Command for ClearCommand {
     }
 }
 
-pub struct RelayComand;
+pub struct RelayComand {
+    relays: Relays,
+}
+
+impl RelayComand {
+    pub fn new(relays: Relays) -> Self {
+        Self { relays }
+    }
+}
 
 #[async_trait]
 impl Command for RelayComand {
@@ -43,7 +51,7 @@ impl Command for RelayComand {
         match args[..] {
             ["add", ip] => {
                 if let Ok(ip) = SocketAddr::from_str(ip) {
-                    RELAYS.insert(ip, "dfd".to_string());
+                    self.relays.insert(ip, "dfd".to_string());
                     stdout
                         .execute(PrintLn(format!(
                             "relay with ip {} was added successfully",
@@ -58,11 +66,11 @@ impl Command for RelayComand {
             }
 
             ["list"] => {
-                RELAYS.sync();
+                self.relays.sync();
 
                 let mut n = 0;
 
-                for entry in RELAYS.iter() {
+                for entry in self.relays.iter() {
                     stdout
                         .execute(PrintLn(format!("{} -> {}", entry.key(), entry.value())))
                         .await?;
@@ -1,62 +1,70 @@
 use ::console::{CommandExecutor, Console, op::PrintLn};
 use clap::Parser;
 use console::{ClearCommand, RelayComand};
-use http::{ApiHandler, App, Request, Response, async_trait};
-use hyper::{Method, StatusCode};
+use http::{App, Compression, Cors, Listener, Metrics, Protocol, Tls, cookie::Key};
 use mini_moka::sync::Cache;
-use nanoid::nanoid;
-use payload::{LoginRequestBody, LoginResponseBody};
-use std::{io::Write, net::SocketAddr, sync::LazyLock};
-use traccia::{Hook, LogLevel, TargetId, error, fatal, info};
+use stats::Stats;
+use std::{
+    future::pending,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::{
+    io,
+    signal::unix::{SignalKind, signal},
+    task::JoinHandle,
+};
+use traccia::{Hook, LogLevel, TargetId, error, fatal, info, warn};
 
 mod console;
 mod payload;
+mod routes;
+mod stats;
 
-struct ServerHandler;
+/// Relays known to this server, shared by the console and the routes.
+pub type Relays = Cache<SocketAddr, String>;
 
-pub static RELAYS: LazyLock<Cache<SocketAddr, String>> = LazyLock::new(|| Cache::new(100));
-
-#[async_trait]
-impl ApiHandler for ServerHandler {
-    async fn incoming(&self, req: Request) -> Result<Response, hyper::Error> {
-        let method = req.method();
-        let segments = req.segments();
-
-        match (method, &segments[..]) {
-            (&Method::POST, ["session"]) => {
-                let body = req.json::<LoginRequestBody>().await.unwrap();
+#[derive(Debug, Parser)]
+#[command(about, author, version)]
+struct Args {
+    /// The addresses the server will use, can be repeated (e.g. 0.0.0.0 and ::)
+    #[arg(short, long = "addr", default_value = "127.0.0.1")]
+    addrs: Vec<String>,
 
-                let id = nanoid!();
-                let username = body.username;
+    /// The port that the server will listen on, required unless socket activated
+    #[arg(short, long)]
+    port: Option<u16>,
 
-                info!("New login: {}", username);
+    /// Unix domain socket to also listen on, e.g. for local admin tools
+    #[arg(long)]
+    unix: Option<PathBuf>,
 
-                let res = Response::empty()
-                    .status(StatusCode::CREATED)
-                    .body(LoginResponseBody { id, username })
-                    .unwrap();
+    /// Origins allowed to make cross-origin requests, can be repeated
+    #[arg(long = "origin", default_value = "http://localhost:5173")]
+    origins: Vec<String>,
 
-                Ok(res)
-            }
+    /// PEM certificate chain, serves HTTPS when given along with --key
+    #[arg(long, requires = "key")]
+    cert: Option<PathBuf>,
 
-            // Cors
-            (&Method::OPTIONS, _) => Ok(Response::empty().status(StatusCode::OK)),
+    /// PEM private key of the certificate
+    #[arg(long, requires = "cert")]
+    key: Option<PathBuf>,
 
-            _ => Ok(Response::empty().status(StatusCode::NOT_FOUND)),
-        }
-    }
-}
+    /// Directory of the built frontend (e.g. www/dist) to serve along the API
+    #[arg(long)]
+    www: Option<PathBuf>,
 
-#[derive(Debug, Parser)]
-#[command(about, author, version)]
-struct Args {
-    /// The address the server will use
-    #[arg(short, long, default_value = "127.0.0.1")]
-    addr: String,
+    /// Path to serve Prometheus metrics on (e.g. /metrics), not served when omitted
+    #[arg(long)]
+    metrics: Option<String>,
 
-    /// The port that the server will listen on
-    #[arg(short, long)]
-    port: u16,
+    /// Secret of at least 64 bytes encrypting the session cookies, shared by every instance.
+    /// Debug builds generate one on every start when omitted
+    #[arg(long, env = "COOKIE_KEY", hide_env_values = true)]
+    cookie_key: Option<String>,
 }
 
 fn default_level() -> LogLevel {
@@ -96,29 +104,142 @@ fn setup_logger() {
     })));
 }
 
+/// Resolves when the console is closed with its exit signal,
+/// or the process receives SIGINT or SIGTERM.
+async fn shutdown_signal(console: JoinHandle<io::Result<()>>) {
+    let console = async {
+        match console.await {
+            Ok(Ok(())) => info!("Console closed, shutting down"),
+
+            // No usable terminal (e.g. running under a supervisor), only signals can stop the server
+            Ok(Err(e)) => {
+                warn!("Console unavailable: {}", e);
+                pending::<()>().await;
+            }
+            Err(_) => pending::<()>().await,
+        }
+    };
+
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+                info!("Received SIGTERM, shutting down");
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                pending::<()>().await;
+            }
+        }
+    };
+
+    let interrupt = async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!("Received SIGINT, shutting down");
+        } else {
+            pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        _ = console => {},
+        _ = terminate => {},
+        _ = interrupt => {},
+    }
+}
+
+/// The key encrypting the session cookies, so that sessions survive restarts
+/// and any instance behind a load balancer can read them.
+fn cookie_key(secret: Option<&str>) -> Result<Key, String> {
+    match secret {
+        Some(secret) => {
+            Key::try_from(secret.as_bytes()).map_err(|e| format!("Invalid cookie key: {}", e))
+        }
+        None if cfg!(debug_assertions) => {
+            warn!("No cookie key given, generating one: every restart logs everyone out");
+            Ok(Key::generate())
+        }
+        None => Err("No cookie key given, pass --cookie-key or set COOKIE_KEY".to_string()),
+    }
+}
+
+/// The sockets passed by the supervisor when socket activated,
+/// otherwise the ones bound from the command line.
+async fn listeners(args: &Args) -> Result<Vec<Listener>, String> {
+    let activated =
+        Listener::from_env().map_err(|e| format!("Invalid socket activation: {}", e))?;
+
+    if !activated.is_empty() {
+        return Ok(activated);
+    }
+
+    let mut listeners = Vec::new();
+
+    if let Some(port) = args.port {
+        for addr in &args.addrs {
+            let addr: SocketAddr = match addr.parse::<IpAddr>() {
+                Ok(ip) => SocketAddr::new(ip, port),
+                Err(_) => return Err(format!("Invalid address: {}", addr)),
+            };
+
+            let listener = Listener::bind(addr)
+                .await
+                .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+
+            listeners.push(listener);
+        }
+    }
+
+    if let Some(ref path) = args.unix {
+        let listener = Listener::bind_unix(path)
+            .map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?;
+
+        listeners.push(listener);
+    }
+
+    Ok(listeners)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     setup_logger();
 
     let args = Args::parse();
 
-    let addr_str = format!("{}:{}", args.addr, args.port);
+    let listeners = match listeners(&args).await {
+        Ok(listeners) if listeners.is_empty() => {
+            fatal!("Nothing to listen on, pass --port or start the server socket activated");
+            return Ok(());
+        }
+        Ok(listeners) => listeners,
+        Err(e) => {
+            fatal!("{}", e);
+            return Ok(());
+        }
+    };
 
-    let addr: SocketAddr = match addr_str.parse() {
-        Ok(addr) => addr,
-        Err(_) => {
-            fatal!("Invalid address: {}", addr_str);
+    let key = match cookie_key(args.cookie_key.as_deref()) {
+        Ok(key) => key,
+        Err(e) => {
+            fatal!("{}", e);
             return Ok(());
         }
     };
 
-    _ = tokio::spawn(
+    let relays = Relays::new(100);
+
+    // Recorded even when not served, the path only controls exposing them
+    let metrics = Metrics::new().path(args.metrics.as_deref());
+    let stats = Stats::default();
+    stats.register(&metrics);
+
+    let console = tokio::spawn(
         Console::new()
             .case_sensitive(false)
             .prompt("> ")
             .prompt_on_start(false)
             .command(ClearCommand)
-            .command(RelayComand)
+            .command(RelayComand::new(relays.clone()))
             .default_callback(|mut stdout, bad| async move {
                 stdout
                     .execute(PrintLn(format!("Unknown command '{}'.", bad)))
@@ -127,13 +248,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             .run(),
     );
 
-    if let Ok(app) = App::new(addr).await {
-        info!("Server listening on {}", addr);
+    let cors = args
+        .origins
+        .into_iter()
+        .fold(Cors::new().allow_credentials(true), Cors::allow_origin);
+
+    let mut app = App::from_listeners(listeners);
 
-        if let Err(e) = app.run(ServerHandler).await {
-            fatal!("There was an error during the main app loop: {}", e);
+    if let (Some(cert), Some(key)) = (args.cert, args.key) {
+        match Tls::from_pem_files(&cert, &key) {
+            Ok(tls) => app = app.tls(tls.reload_every(Duration::from_secs(60))),
+            Err(e) => {
+                fatal!("Failed to load TLS certificate: {}", e);
+                return Ok(());
+            }
         }
     }
 
+    for listener in app.listeners() {
+        info!("Server listening on {}", listener);
+    }
+
+    match app
+        .cors(cors)
+        .compression(Compression::new())
+        .protocol(Protocol::Auto)
+        .handler_timeout(Duration::from_secs(30))
+        .metrics(metrics)
+        .state(relays)
+        .state(stats)
+        .state(key)
+        .shutdown_signal(shutdown_signal(console))
+        .run(routes::router(args.www))
+        .await
+    {
+        Ok(report) => info!(
+            "Server stopped, {} connections drained, {} force-closed",
+            report.drained, report.forced
+        ),
+        Err(e) => fatal!("There was an error during the main app loop: {}", e),
+    }
+
     Ok(())
 }
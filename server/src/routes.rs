@@ -0,0 +1,145 @@
+use crate::{
+    payload::{LoginRequestBody, LoginResponseBody},
+    stats::Stats,
+};
+use http::{
+    BodyLimit, Error, Format, RateLimit, Response, Router, StaticFiles,
+    cookie::{Cookie, Key, SameSite},
+    extract::{Payload, State},
+    handler,
+};
+use hyper::StatusCode;
+use nanoid::nanoid;
+use std::{path::PathBuf, time::Duration};
+use traccia::info;
+
+/// Builds the API routes, serving the frontend build from `www` for any other path.
+pub fn router(www: Option<PathBuf>) -> Router {
+    // Each login mints a new session, so keep clients from minting them in bulk
+    let login = RateLimit::new(10, Duration::from_secs(60))
+        .wrap(BodyLimit::new(1024, handler(create_session)));
+    let router = Router::new().post("/session", login);
+
+    match www {
+        Some(dir) => router.fallback(StaticFiles::new(dir).spa_fallback(true)),
+        None => router,
+    }
+}
+
+async fn create_session(
+    State(key): State<Key>,
+    State(stats): State<Stats>,
+    format: Format,
+    Payload(body): Payload<LoginRequestBody>,
+) -> Result<Response, Error> {
+    let id = nanoid!();
+    let username = body.username;
+
+    info!("New login: {}", username);
+    stats.sessions.inc();
+
+    let session = Cookie::build(("session", id.clone()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax);
+
+    Response::empty()
+        .status(StatusCode::CREATED)
+        .private_cookie(session, &key)
+        .body_as(format, LoginResponseBody { id, username })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::testing::TestClient;
+    use serde_json::{Value, json};
+
+    fn client() -> TestClient<Router> {
+        TestClient::new(router(None))
+            .state(Key::generate())
+            .state(Stats::default())
+    }
+
+    #[tokio::test]
+    async fn creates_a_session() {
+        let client = client();
+
+        let res = client
+            .post("/session")
+            .peer_addr("10.0.0.1:5000".parse().unwrap())
+            .json(&json!({ "username": "alice" }))
+            .send()
+            .await;
+
+        res.assert_status(StatusCode::CREATED);
+
+        let body = res.json::<Value>();
+        assert_eq!(body["username"], "alice");
+
+        let cookie = res.header("set-cookie").expect("no session cookie");
+        assert!(cookie.starts_with("session="), "{}", cookie);
+        assert!(cookie.contains("HttpOnly"), "{}", cookie);
+
+        // The cookie is encrypted, the id only travels in the body
+        assert!(!cookie.contains(body["id"].as_str().unwrap()), "{}", cookie);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_bodies() {
+        let client = client();
+
+        for body in [r#"{"username": 42}"#, "{", ""] {
+            client
+                .post("/session")
+                .peer_addr("10.0.0.2:5000".parse().unwrap())
+                .header("content-type", "application/json")
+                .body(body)
+                .send()
+                .await
+                .assert_status(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_logins_per_client() {
+        let client = client();
+
+        let login = |ip: &str| {
+            client
+                .post("/session")
+                .peer_addr(format!("{}:5000", ip).parse().unwrap())
+                .json(&json!({ "username": "bob" }))
+                .send()
+        };
+
+        for _ in 0..10 {
+            login("10.0.0.3").await.assert_status(StatusCode::CREATED);
+        }
+
+        let res = login("10.0.0.3").await;
+        res.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.header("retry-after").is_some());
+
+        // Other clients keep their own budget
+        login("10.0.0.4").await.assert_status(StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn counts_sessions() {
+        let stats = Stats::default();
+        let client = TestClient::new(router(None))
+            .state(Key::generate())
+            .state(stats.clone());
+
+        client
+            .post("/session")
+            .peer_addr("10.0.0.5:5000".parse().unwrap())
+            .json(&json!({ "username": "carol" }))
+            .send()
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        assert_eq!(stats.sessions.get(), 1);
+    }
+}
@@ -0,0 +1,18 @@
+use http::{Metrics, metrics::prometheus_client::metrics::counter::Counter};
+
+/// Game counters, exposed along the HTTP metrics.
+#[derive(Clone, Default)]
+pub struct Stats {
+    pub sessions: Counter,
+}
+
+impl Stats {
+    /// Adds the counters to the registry of `metrics`.
+    pub fn register(&self, metrics: &Metrics) {
+        metrics.register(
+            "casino_sessions",
+            "Sessions created since start",
+            self.sessions.clone(),
+        );
+    }
+}